@@ -0,0 +1,191 @@
+// Aho-Corasick: matching many literal patterns in a single pass
+
+// Calling `line.contains(query)` once per pattern costs one pass over the input
+// for every pattern. With thousands of patterns (e.g. a list of banned
+// identifiers) that gets slow quickly.
+
+// Aho-Corasick builds a trie out of all the patterns and adds a "failure link"
+// to every node: the longest proper suffix of the current path that is also a
+// prefix of some pattern. While scanning, a mismatch follows failure links
+// instead of restarting, so every byte of the input is looked at once no
+// matter how many patterns there are.
+
+use std::collections::{ HashMap, VecDeque };
+
+#[derive(Debug, PartialEq)]
+pub struct PatternMatch {
+    pub pattern: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Default, Clone)]
+struct Node {
+    next: HashMap<u8, usize>,
+    fail: usize,
+    // indexes of every pattern that ends at this node, including the ones
+    // inherited through the failure link
+    outputs: Vec<usize>,
+}
+
+#[derive(Clone)]
+pub struct PatternSet {
+    patterns: Vec<String>,
+    nodes: Vec<Node>,
+}
+
+impl PatternSet {
+    pub fn new(patterns: Vec<String>) -> PatternSet {
+        let mut nodes = vec![Node::default()];
+
+        // 1. insert every pattern into the trie
+        for (index, pattern) in patterns.iter().enumerate() {
+            let mut current = 0;
+            for &byte in pattern.as_bytes() {
+                current = match nodes[current].next.get(&byte) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(Node::default());
+                        let child = nodes.len() - 1;
+                        nodes[current].next.insert(byte, child);
+                        child
+                    }
+                };
+            }
+            nodes[current].outputs.push(index);
+        }
+
+        // 2. compute failure links breadth first, so a node's failure target
+        // (which is always shallower) is finished before the node itself
+        let mut queue = VecDeque::new();
+        let root_children: Vec<usize> = nodes[0].next.values().copied().collect();
+        for child in root_children {
+            nodes[child].fail = 0;
+            queue.push_back(child);
+        }
+
+        while let Some(current) = queue.pop_front() {
+            let edges: Vec<(u8, usize)> = nodes[current].next
+                .iter()
+                .map(|(&byte, &child)| (byte, child))
+                .collect();
+
+            for (byte, child) in edges {
+                let mut fallback = nodes[current].fail;
+                while fallback != 0 && !nodes[fallback].next.contains_key(&byte) {
+                    fallback = nodes[fallback].fail;
+                }
+                let fail = nodes[fallback].next.get(&byte).copied().unwrap_or(0);
+
+                nodes[child].fail = fail;
+                let inherited = nodes[fail].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        PatternSet { patterns, nodes }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    fn step(&self, mut state: usize, byte: u8) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[state].next.get(&byte) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.nodes[state].fail;
+        }
+    }
+
+    // Every (possibly overlapping) occurrence of every pattern, ordered by
+    // where the match ends.
+    pub fn find_iter(&self, haystack: &str) -> Vec<PatternMatch> {
+        let mut matches: Vec<PatternMatch> = self.nodes[0].outputs
+            .iter()
+            .map(|&pattern| PatternMatch { pattern, start: 0, end: 0 })
+            .collect();
+
+        let mut state = 0;
+        for (i, &byte) in haystack.as_bytes().iter().enumerate() {
+            state = self.step(state, byte);
+            for &pattern in &self.nodes[state].outputs {
+                let end = i + 1;
+                matches.push(PatternMatch {
+                    pattern,
+                    start: end - self.patterns[pattern].len(),
+                    end,
+                });
+            }
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(patterns: &[&str]) -> PatternSet {
+        PatternSet::new(
+            patterns
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        )
+    }
+
+    #[test]
+    fn finds_overlapping_patterns() {
+        let patterns = set(&["he", "she", "his", "hers"]);
+
+        let found: Vec<(usize, usize, usize)> = patterns
+            .find_iter("ushers")
+            .iter()
+            .map(|m| (m.pattern, m.start, m.end))
+            .collect();
+
+        assert_eq!(vec![(1, 1, 4), (0, 2, 4), (3, 2, 6)], found);
+    }
+
+    #[test]
+    fn finds_what_contains_finds() {
+        let patterns = set(&["duct", "three", "xyz"]);
+
+        assert!(!patterns.find_iter("safe, fast, productive.").is_empty());
+        assert!(!patterns.find_iter("Pick three.").is_empty());
+        assert!(patterns.find_iter("Rust:").is_empty());
+    }
+
+    #[test]
+    fn empty_pattern_matches_every_line() {
+        let patterns = set(&["", "abc"]);
+
+        assert!(!patterns.find_iter("anything").is_empty());
+        assert_eq!(0, patterns.find_iter("x")[0].pattern);
+    }
+
+    #[test]
+    fn thousands_of_patterns() {
+        let banned: Vec<String> = (0..5000).map(|i| format!("ident_{i}")).collect();
+        let patterns = PatternSet::new(banned);
+
+        assert!(!patterns.find_iter("let ident_4242 = 1;").is_empty());
+        assert!(patterns.find_iter("let ident_ = 1;").is_empty());
+        assert_eq!(4242, patterns.find_iter("ident_4242;").last().unwrap().pattern);
+    }
+}
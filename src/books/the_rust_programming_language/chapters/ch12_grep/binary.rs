@@ -0,0 +1,70 @@
+// Binary files
+
+// Text files practically never contain a NUL byte, while executables, images,
+// databases and the like almost always have one early on. Like grep, we only
+// look at the first block of the file to decide, so a huge binary doesn't have
+// to be scanned twice.
+
+// What to do once a file looks binary (--binary-files=TYPE):
+//   binary        (default) search it, but only print "Binary file X matches"
+//   text          (-a) treat it like any other file and print the lines
+//   without-match skip it
+
+pub const BLOCK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryFiles {
+    Binary,
+    Text,
+    WithoutMatch,
+}
+
+impl BinaryFiles {
+    pub fn parse(name: &str) -> Option<BinaryFiles> {
+        match name {
+            "binary" => Some(BinaryFiles::Binary),
+            "text" => Some(BinaryFiles::Text),
+            "without-match" => Some(BinaryFiles::WithoutMatch),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let first_block = &bytes[..bytes.len().min(BLOCK_SIZE)];
+    first_block.contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nul_in_the_first_block_means_binary() {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0];
+        elf.extend(b"error: not found");
+
+        assert!(is_binary(&elf));
+        assert!(!is_binary("Здравствуйте\n\ttabs and ümlauts".as_bytes()));
+        assert!(!is_binary(b""));
+    }
+
+    #[test]
+    fn only_the_first_block_is_checked() {
+        let mut late_nul = vec![b'a'; BLOCK_SIZE];
+        late_nul.push(0);
+
+        assert!(!is_binary(&late_nul));
+
+        late_nul[BLOCK_SIZE - 1] = 0;
+        assert!(is_binary(&late_nul));
+    }
+
+    #[test]
+    fn parses_policy_names() {
+        assert_eq!(Some(BinaryFiles::Text), BinaryFiles::parse("text"));
+        assert_eq!(Some(BinaryFiles::WithoutMatch), BinaryFiles::parse("without-match"));
+        assert_eq!(Some(BinaryFiles::Binary), BinaryFiles::parse("binary"));
+        assert_eq!(None, BinaryFiles::parse("nope"));
+    }
+}
@@ -0,0 +1,305 @@
+// The user's config file
+
+// Options used on every search can go in a file instead of being typed every
+// time. It's looked for at $MINIGREP_CONFIG, or else at
+// ~/.config/minigrep/config, and `--no-config` skips it.
+//
+//   # anything after a '#' is a comment
+//   [flags]
+//   --column
+//   --encoding latin1
+//
+//   [color]
+//   when = auto
+//   match = red
+//   path = magenta
+//
+//   [ignore]
+//   target
+//   *.min.js
+//
+//   [types]
+//   rust = *.rs
+//   web = *.html, *.css
+//
+// The file is turned into command line arguments that go in front of the real
+// ones, so `Config::build` handles both the same way. Because the last
+// occurrence of an option wins (`--encoding utf-16le` on the command line
+// replaces the one above), the command line overrides the config file. A
+// switch like `--column` is undone with its `--no-` form: `--no-column`.
+// Each line is checked on its own as it's read, so a bad option or value is
+// reported with the file and line it's on. Options that make a different
+// kind of run (-j, --async, --follow, --replace, --backup, --fuzzy, --index)
+// aren't allowed: every plain search would then fail in `Config::build`, and
+// most of them can't be turned back off on the command line.
+
+use std::{ env, error::Error, fmt, fs, io, path::{ Path, PathBuf } };
+
+use super::{ Options, glob::{ self, Glob } };
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Flags,
+    Color,
+    Ignore,
+    Types,
+}
+
+// Where the config file is, if there is one to look for.
+pub fn locate() -> Option<(PathBuf, bool)> {
+    if let Ok(path) = env::var("MINIGREP_CONFIG") {
+        // asked for explicitly, so it has to exist
+        return Some((PathBuf::from(path), true));
+    }
+
+    let home = env::var("HOME").ok()?;
+    Some((Path::new(&home).join(".config/minigrep/config"), false))
+}
+
+// The arguments the config file stands for, none if there is no file.
+pub fn load() -> Result<Vec<String>, Box<dyn Error>> {
+    let Some((path, required)) = locate() else {
+        return Ok(Vec::new());
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(parse(&path, &text)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Vec::new()),
+        Err(e) => Err(format!("{}: {e}", path.display()).into()),
+    }
+}
+
+pub fn parse(path: &Path, text: &str) -> Result<Vec<String>, ParseError> {
+    let mut args = Vec::new();
+    let mut section = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| ParseError { path: path.to_path_buf(), line: index + 1, message };
+
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = Some(match name.trim() {
+                "flags" => Section::Flags,
+                "color" => Section::Color,
+                "ignore" => Section::Ignore,
+                "types" => Section::Types,
+                other => {
+                    return Err(error(format!("unknown section [{other}]")));
+                }
+            });
+            continue;
+        }
+
+        let Some(section) = section else {
+            return Err(error(String::from("expected a [section] first")));
+        };
+
+        let line_args = match section {
+            // exactly what would be typed on the command line, but only
+            // options: a query or a path here would take the place of the
+            // real ones
+            Section::Flags => {
+                if !line.starts_with('-') {
+                    return Err(error(format!("expected a flag, got {line}")));
+                }
+                line.split_whitespace().map(String::from).collect()
+            }
+            Section::Color => {
+                let (key, value) = key_value(line).ok_or_else(|| {
+                    error(format!("expected key = value, got {line}"))
+                })?;
+                match key {
+                    "when" => vec![String::from("--color"), value.to_string()],
+                    "match" | "path" => vec![String::from("--colors"), format!("{key}:{value}")],
+                    _ => {
+                        return Err(error(format!("unknown color setting {key}")));
+                    }
+                }
+            }
+            Section::Ignore => {
+                Glob::new(line).map_err(error)?;
+                vec![String::from("--ignore"), line.to_string()]
+            }
+            Section::Types => {
+                let (name, globs) = key_value(line).ok_or_else(|| {
+                    error(format!("expected name = globs, got {line}"))
+                })?;
+                let globs = glob::split_list(globs);
+                for glob in &globs {
+                    Glob::new(glob).map_err(error)?;
+                }
+                vec![String::from("--type-add"), format!("{name}:{}", globs.join(","))]
+            }
+        };
+
+        // checked here, where the file and line are known, instead of later
+        // by `Config::build`
+        check(&line_args).map_err(|message| error(message.to_string()))?;
+        args.extend(line_args);
+    }
+
+    Ok(args)
+}
+
+// The arguments of one line, each option known and given its value, and
+// nothing else.
+fn check(line_args: &[String]) -> Result<(), &'static str> {
+    let mut options = Options::new();
+    let mut line_args = line_args.iter().cloned();
+    while let Some(arg) = line_args.next() {
+        options.apply(arg, &mut line_args)?;
+    }
+
+    if !options.positional.is_empty() {
+        return Err("expected only options, a query or a path here would take the place of the real ones");
+    }
+    if !options.patterns.is_empty() || !options.pattern_files.is_empty() {
+        return Err("-e and -f can't go in the config file, they would take the place of the query");
+    }
+    if
+        options.jobs.is_some() ||
+        options.async_search ||
+        options.follow ||
+        options.replace.is_some() ||
+        options.backup.is_some() ||
+        options.fuzzy.is_some() ||
+        options.index_dir.is_some()
+    {
+        return Err(
+            "-j, --async, --follow, --replace, --backup, --fuzzy and --index can't go in the config file, plain searches would fail"
+        );
+    }
+    Ok(())
+}
+
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('=')?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() || value.is_empty() {
+        return None;
+    }
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(text: &str) -> Result<Vec<String>, ParseError> {
+        parse(Path::new("config"), text)
+    }
+
+    #[test]
+    fn turns_sections_into_arguments() {
+        let args = parse_str(
+            "\
+# defaults
+[flags]
+--column
+--encoding latin1   # old files
+
+[color]
+when = always
+match = red
+
+[ignore]
+target
+
+[types]
+web = *.html, *.{css,scss}
+"
+        ).unwrap();
+
+        assert_eq!(
+            vec![
+                "--column",
+                "--encoding",
+                "latin1",
+                "--color",
+                "always",
+                "--colors",
+                "match:red",
+                "--ignore",
+                "target",
+                "--type-add",
+                "web:*.html,*.{css,scss}"
+            ],
+            args
+        );
+    }
+
+    #[test]
+    fn errors_name_the_file_and_line() {
+        let error = parse_str("[flags]\n--column\n\n[colour]\n").unwrap_err();
+
+        assert_eq!(4, error.line);
+        assert_eq!("config:4: unknown section [colour]", error.to_string());
+    }
+
+    #[test]
+    fn rejects_what_isnt_an_option() {
+        assert_eq!(1, parse_str("--column\n").unwrap_err().line);
+        assert_eq!(2, parse_str("[flags]\nrust poem.txt\n").unwrap_err().line);
+        assert_eq!(2, parse_str("[color]\nmatch red\n").unwrap_err().line);
+        assert_eq!(2, parse_str("[types]\nweb = *.{html\n").unwrap_err().line);
+    }
+
+    #[test]
+    fn checks_every_option_and_value() {
+        for text in [
+            "[flags]\n--colum\n",
+            "[flags]\n--encoding klingon\n",
+            "[flags]\n--jobs\n",
+            "[flags]\n--column poem.txt\n",
+            "[flags]\n--json -- duct\n",
+            "[flags]\n-e duct\n",
+            "[color]\nwhen = sometimes\n",
+            "[color]\nmatch = mauve\n",
+        ] {
+            let error = parse_str(text).unwrap_err();
+            assert_eq!(2, error.line, "{text}");
+            assert!(error.to_string().starts_with("config:2: "), "{error}");
+        }
+        assert_eq!(
+            "config:2: Didn't get an encoding after --encoding",
+            parse_str("[flags]\n--encoding\n").unwrap_err().to_string()
+        );
+
+        assert_eq!(vec!["--column", "--json"], parse_str("[flags]\n--column --json\n").unwrap());
+    }
+
+    #[test]
+    fn rejects_options_a_plain_search_cant_undo() {
+        for flags in ["-j 4", "--jobs 4", "--async", "--follow", "--replace x", "--backup .bak", "--fuzzy 1", "--index docs"] {
+            let error = parse_str(&format!("[flags]\n--column\n{flags}\n")).unwrap_err();
+            assert_eq!(3, error.line, "{flags}");
+        }
+
+        // what's left still lets the simplest search through
+        let mut args = vec![String::from("minigrep")];
+        args.extend(parse_str("[flags]\n--column\n--encoding latin1\n[color]\nwhen = never\n").unwrap());
+        args.extend([String::from("duct"), String::from("poem.txt")]);
+        let config = super::super::Config::build(args.into_iter()).unwrap();
+        assert_eq!(vec!["duct"], config.patterns);
+        assert!(config.column);
+    }
+}
@@ -0,0 +1,173 @@
+// Searching compressed files (-z / --search-zip)
+
+// Rotated logs are usually compressed. Instead of trusting the file extension
+// we look at the first bytes of the file ("magic bytes"), which every gzip and
+// zstd stream starts with:
+//
+//   gzip: 1f 8b
+//   zstd: 28 b5 2f fd
+//
+// The decoders from `flate2` and `zstd` implement `Read`, so the rest of the
+// program reads decompressed text exactly like it reads a plain file, and line
+// numbers count lines of the decompressed content.
+
+use std::{ fs::File, io::{ self, BufRead, BufReader, Read } };
+
+use flate2::bufread::MultiGzDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+pub fn detect(header: &[u8]) -> Compression {
+    if header.starts_with(&GZIP_MAGIC) {
+        Compression::Gzip
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    }
+}
+
+// Opens `path` for reading. With `search_zip` off, the file is always read
+// as-is, even if it happens to be compressed.
+pub fn open(path: &str, search_zip: bool) -> io::Result<(Box<dyn BufRead>, Compression)> {
+    wrap(File::open(path)?, search_zip)
+}
+
+// Same as `open`, for any source of bytes (e.g. standard input).
+pub fn wrap(source: impl Read + 'static, search_zip: bool) -> io::Result<(Box<dyn BufRead>, Compression)> {
+    let mut reader = BufReader::new(source);
+
+    if !search_zip {
+        return Ok((Box::new(reader), Compression::None));
+    }
+
+    // `fill_buf` lets us peek at the first bytes without consuming them, so
+    // the decoder (or the plain reader) still sees the whole input
+    let compression = detect(reader.fill_buf()?);
+
+    let reader: Box<dyn BufRead> = match compression {
+        // `MultiGzDecoder` also reads files made of several gzip members
+        // glued together, e.g. `cat a.gz b.gz > all.gz`
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        Compression::None => Box::new(reader),
+    };
+
+    Ok((reader, compression))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ env, fs, io::Write, path::{ Path, PathBuf } };
+
+    use flate2::{ Compression as Level, write::GzEncoder };
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+";
+
+    fn fixture(name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-zip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read(path: &Path, search_zip: bool) -> (String, Compression) {
+        let (mut reader, compression) = open(path.to_str().unwrap(), search_zip).unwrap();
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).unwrap();
+        (contents, compression)
+    }
+
+    #[test]
+    fn detects_by_magic_bytes() {
+        assert_eq!(Compression::Gzip, detect(&gzip("x")));
+        assert_eq!(Compression::Zstd, detect(&zstd::encode_all("x".as_bytes(), 0).unwrap()));
+        assert_eq!(Compression::None, detect(b"plain text"));
+        assert_eq!(Compression::None, detect(b""));
+    }
+
+    #[test]
+    fn reads_gzip() {
+        // the name doesn't matter, only the content does
+        let path = fixture("poem.log.1", &gzip(POEM));
+
+        assert_eq!((POEM.to_string(), Compression::Gzip), read(&path, true));
+    }
+
+    #[test]
+    fn reads_concatenated_gzip_members() {
+        let mut bytes = gzip("first\n");
+        bytes.extend(gzip("second\n"));
+        let path = fixture("rotated.gz", &bytes);
+
+        assert_eq!("first\nsecond\n", read(&path, true).0);
+    }
+
+    #[test]
+    fn reads_zstd() {
+        let path = fixture("poem.zst", &zstd::encode_all(POEM.as_bytes(), 3).unwrap());
+
+        assert_eq!((POEM.to_string(), Compression::Zstd), read(&path, true));
+    }
+
+    #[test]
+    fn plain_files_pass_through() {
+        let path = fixture("poem.txt", POEM.as_bytes());
+
+        assert_eq!((POEM.to_string(), Compression::None), read(&path, true));
+    }
+
+    #[test]
+    fn without_search_zip_nothing_is_decompressed() {
+        let path = fixture("raw.gz", &gzip(POEM));
+        let (mut reader, compression) = open(path.to_str().unwrap(), false).unwrap();
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+
+        assert_eq!(Compression::None, compression);
+        assert_eq!(gzip(POEM), bytes);
+    }
+
+    #[test]
+    fn wraps_any_reader() {
+        let (mut reader, compression) = wrap(io::Cursor::new(gzip(POEM)), true).unwrap();
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).unwrap();
+
+        assert_eq!((POEM.to_string(), Compression::Gzip), (contents, compression));
+    }
+
+    #[test]
+    fn line_numbers_count_decompressed_lines() {
+        use super::super::{ MatchMode, search_patterns, PatternSet };
+
+        let path = fixture("numbers.gz", &gzip(POEM));
+        let (contents, _) = read(&path, true);
+        let patterns = PatternSet::new(vec![String::from("banish")]);
+
+        let hits = search_patterns(&patterns, MatchMode::Substring, &contents);
+
+        assert_eq!(4, hits[0].line_number);
+    }
+}
@@ -0,0 +1,286 @@
+// Text encodings (--encoding)
+
+// Everything minigrep matches is a `&str`, so it has to be UTF-8. Plenty of
+// files aren't: Windows tools like to write UTF-16, and older files are often
+// Latin-1 (ISO-8859-1). Those get decoded into UTF-8 on the way in, so the
+// search itself, and everything it prints, is UTF-8 no matter what the file
+// was.
+
+// Which encoding a file uses can't really be known from its bytes, except
+// when it starts with a byte order mark (BOM), the char U+FEFF encoded in the
+// file's encoding:
+//
+//   UTF-8:    ef bb bf
+//   UTF-16LE: ff fe
+//   UTF-16BE: fe ff
+//
+// Without a BOM we assume UTF-8, and `--encoding NAME` says otherwise.
+
+use std::{ fmt, io::{ self, BufRead, Read } };
+
+const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: [u8; 2] = [0xff, 0xfe];
+const UTF16BE_BOM: [u8; 2] = [0xfe, 0xff];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    // The encoding announced by a BOM at the start of `header`, UTF-8 if
+    // there is none.
+    pub fn detect(header: &[u8]) -> Encoding {
+        if header.starts_with(&UTF16LE_BOM) {
+            Encoding::Utf16Le
+        } else if header.starts_with(&UTF16BE_BOM) {
+            Encoding::Utf16Be
+        } else {
+            Encoding::Utf8
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// Skips a UTF-8 BOM, so it doesn't end up glued to the first line.
+pub fn skip_utf8_bom(reader: &mut impl BufRead) -> io::Result<()> {
+    if reader.fill_buf()?.starts_with(&UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+    }
+    Ok(())
+}
+
+// Reads UTF-16 or Latin-1 from `inner` and hands out the same text as UTF-8.
+// Broken input (half a surrogate pair, an odd number of bytes) becomes
+// U+FFFD and is remembered, see `had_invalid`.
+pub struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    // decoded UTF-8 that `read` hasn't handed out yet
+    decoded: Vec<u8>,
+    // a code unit can be split between two chunks of input, and a surrogate
+    // pair between two code units
+    odd_byte: Option<u8>,
+    high_surrogate: Option<u16>,
+    at_start: bool,
+    at_end: bool,
+    invalid: bool,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Decoder<R> {
+        Decoder {
+            inner,
+            encoding,
+            decoded: Vec::new(),
+            odd_byte: None,
+            high_surrogate: None,
+            at_start: true,
+            at_end: false,
+            invalid: false,
+        }
+    }
+
+    pub fn had_invalid(&self) -> bool {
+        self.invalid
+    }
+
+    fn push(&mut self, c: char) {
+        // the BOM only tells us the encoding, it isn't part of the text
+        if !(self.at_start && c == '\u{FEFF}') {
+            let mut utf8 = [0; 4];
+            self.decoded.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        self.at_start = false;
+    }
+
+    fn push_invalid(&mut self) {
+        self.invalid = true;
+        self.push(char::REPLACEMENT_CHARACTER);
+    }
+
+    fn push_unit(&mut self, unit: u16) {
+        let is_high = (0xd800..0xdc00).contains(&unit);
+        let is_low = (0xdc00..0xe000).contains(&unit);
+
+        match self.high_surrogate.take() {
+            Some(high) if is_low => {
+                let c = 0x10000 + ((u32::from(high) - 0xd800) << 10) + (u32::from(unit) - 0xdc00);
+                self.push(char::from_u32(c).unwrap());
+                return;
+            }
+            // a high surrogate not followed by a low one
+            Some(_) => self.push_invalid(),
+            None => {}
+        }
+
+        if is_high {
+            self.high_surrogate = Some(unit);
+        } else if is_low {
+            self.push_invalid();
+        } else {
+            // everything outside the surrogate range is a char of its own
+            self.push(char::from_u32(u32::from(unit)).unwrap());
+        }
+    }
+
+    // Decodes the next chunk of input, or finishes up at the end of it.
+    fn decode_chunk(&mut self) -> io::Result<()> {
+        let chunk = self.inner.fill_buf()?.to_vec();
+        self.inner.consume(chunk.len());
+
+        if chunk.is_empty() {
+            self.at_end = true;
+            if self.odd_byte.take().is_some() || self.high_surrogate.take().is_some() {
+                self.push_invalid();
+            }
+            return Ok(());
+        }
+
+        match self.encoding {
+            // every byte is the char with the same number
+            Encoding::Latin1 => {
+                for byte in chunk {
+                    self.push(char::from(byte));
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                for byte in chunk {
+                    let Some(first) = self.odd_byte.take() else {
+                        self.odd_byte = Some(byte);
+                        continue;
+                    };
+                    let unit = if self.encoding == Encoding::Utf16Le {
+                        u16::from_le_bytes([first, byte])
+                    } else {
+                        u16::from_be_bytes([first, byte])
+                    };
+                    self.push_unit(unit);
+                }
+            }
+            // UTF-8 never needs decoding, `Decoder` isn't used for it
+            Encoding::Utf8 => self.decoded.extend(chunk),
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded.is_empty() && !self.at_end {
+            self.decode_chunk()?;
+        }
+
+        let n = buf.len().min(self.decoded.len());
+        buf[..n].copy_from_slice(&self.decoded[..n]);
+        self.decoded.drain(..n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        let mut bytes = UTF16LE_BOM.to_vec();
+        for unit in text.encode_utf16() {
+            bytes.extend(unit.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8], encoding: Encoding) -> (String, bool) {
+        let mut decoder = Decoder::new(bytes, encoding);
+        let mut text = String::new();
+        decoder.read_to_string(&mut text).unwrap();
+        (text, decoder.had_invalid())
+    }
+
+    #[test]
+    fn detects_by_bom() {
+        assert_eq!(Encoding::Utf16Le, Encoding::detect(&utf16le("hi")));
+        assert_eq!(Encoding::Utf16Be, Encoding::detect(&[0xfe, 0xff, 0, b'h']));
+        assert_eq!(Encoding::Utf8, Encoding::detect(&[0xef, 0xbb, 0xbf, b'h']));
+        assert_eq!(Encoding::Utf8, Encoding::detect(b"hi"));
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Some(Encoding::Latin1), Encoding::parse("ISO-8859-1"));
+        assert_eq!(Some(Encoding::Utf16Le), Encoding::parse("utf-16le"));
+        assert_eq!(None, Encoding::parse("ebcdic"));
+    }
+
+    #[test]
+    fn decodes_utf16_without_the_bom() {
+        // 🦀 needs a surrogate pair
+        let text = "Здравствуйте 🦀\r\nRust:\r\n";
+
+        assert_eq!((text.to_string(), false), decode(&utf16le(text), Encoding::Utf16Le));
+
+        let mut big_endian = UTF16BE_BOM.to_vec();
+        for unit in text.encode_utf16() {
+            big_endian.extend(unit.to_be_bytes());
+        }
+        assert_eq!((text.to_string(), false), decode(&big_endian, Encoding::Utf16Be));
+    }
+
+    #[test]
+    fn decodes_across_chunk_boundaries() {
+        let bytes = utf16le("a🦀b");
+        // a reader that hands out one byte at a time
+        let reader = io::BufReader::with_capacity(1, &bytes[..]);
+        let mut text = String::new();
+
+        Decoder::new(reader, Encoding::Utf16Le).read_to_string(&mut text).unwrap();
+
+        assert_eq!("a🦀b", text);
+    }
+
+    #[test]
+    fn broken_utf16_becomes_replacement_chars() {
+        // a lone low surrogate, then a trailing odd byte
+        let bytes = [b'a', 0, 0x00, 0xdc, b'b', 0, b'c'];
+
+        assert_eq!((String::from("a\u{FFFD}b\u{FFFD}"), true), decode(&bytes, Encoding::Utf16Le));
+    }
+
+    #[test]
+    fn decodes_latin1() {
+        assert_eq!((String::from("café"), false), decode(b"caf\xe9", Encoding::Latin1));
+    }
+
+    #[test]
+    fn skips_a_utf8_bom() {
+        let mut reader = &b"\xef\xbb\xbfRust"[..];
+        skip_utf8_bom(&mut reader).unwrap();
+
+        assert_eq!(b"Rust", reader);
+    }
+}
@@ -0,0 +1,170 @@
+// File types (-t, -T, --type-list, --type-add)
+
+// When searching a whole directory, most of the time only some kinds of files
+// are interesting: `-t rust` searches only Rust files, `-T markdown` searches
+// everything except Markdown. A type is a name for a list of globs, matched
+// against the file name (not the whole path), so `Cargo.toml` can be a type of
+// its own next to `*.rs`.
+
+use std::collections::BTreeMap;
+
+use super::glob::Glob;
+
+// The types every minigrep knows, more can be added with --type-add.
+const BUILT_IN: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cargo", &["Cargo.toml", "Cargo.lock"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh"]),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.htm", "*.html"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs"]),
+    ("json", &["*.json"]),
+    ("log", &["*.log"]),
+    ("make", &["Makefile", "makefile", "*.mk"]),
+    ("markdown", &["*.md", "*.markdown"]),
+    ("py", &["*.py"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeTable {
+    // a BTreeMap keeps --type-list sorted by name
+    types: BTreeMap<String, Vec<Glob>>,
+}
+
+impl TypeTable {
+    pub fn with_built_ins() -> TypeTable {
+        let types = BUILT_IN.iter()
+            .map(|(name, globs)| {
+                let globs = globs
+                    .iter()
+                    .map(|glob| Glob::new(glob).unwrap())
+                    .collect();
+                (name.to_string(), globs)
+            })
+            .collect();
+
+        TypeTable { types }
+    }
+
+    // Adding to a type that exists extends it, like ripgrep does, so
+    // `--type-add rust:*.rs.in` keeps `*.rs` too.
+    pub fn add(&mut self, name: &str, globs: Vec<Glob>) {
+        self.types.entry(name.to_string()).or_default().extend(globs);
+    }
+
+    pub fn globs(&self, name: &str) -> Option<&[Glob]> {
+        self.types.get(name).map(Vec::as_slice)
+    }
+
+    // One "name: glob, glob" line per type.
+    pub fn list(&self) -> String {
+        let mut listed = String::new();
+        for (name, globs) in &self.types {
+            let globs: Vec<&str> = globs
+                .iter()
+                .map(Glob::as_str)
+                .collect();
+            listed.push_str(&format!("{name}: {}\n", globs.join(", ")));
+        }
+        listed
+    }
+}
+
+// The -t and -T choices, resolved to their globs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeFilter {
+    select: Vec<Glob>,
+    reject: Vec<Glob>,
+}
+
+impl TypeFilter {
+    // None when one of the names isn't a type in `table`.
+    pub fn new(table: &TypeTable, select: &[String], reject: &[String]) -> Option<TypeFilter> {
+        let resolve = |names: &[String]| -> Option<Vec<Glob>> {
+            let mut globs = Vec::new();
+            for name in names {
+                globs.extend_from_slice(table.globs(name)?);
+            }
+            Some(globs)
+        };
+
+        Some(TypeFilter { select: resolve(select)?, reject: resolve(reject)? })
+    }
+
+    // `path` is relative and '/'-separated, only its last part counts.
+    pub fn allows(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+
+        let selected = self.select.is_empty() || self.select.iter().any(|glob| glob.is_match(name));
+        let rejected = self.reject.iter().any(|glob| glob.is_match(name));
+        selected && !rejected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn selects_and_rejects_by_name() {
+        let table = TypeTable::with_built_ins();
+
+        let rust = TypeFilter::new(&table, &names(&["rust", "cargo"]), &[]).unwrap();
+        assert!(rust.allows("src/main.rs"));
+        assert!(rust.allows("Cargo.toml"));
+        assert!(!rust.allows("readme.md"));
+
+        let no_markdown = TypeFilter::new(&table, &[], &names(&["markdown"])).unwrap();
+        assert!(no_markdown.allows("src/main.rs"));
+        assert!(!no_markdown.allows("docs/NOTES.md"));
+
+        assert!(TypeFilter::default().allows("anything"));
+    }
+
+    #[test]
+    fn unknown_types_are_refused() {
+        let table = TypeTable::with_built_ins();
+
+        assert_eq!(None, TypeFilter::new(&table, &names(&["klingon"]), &[]));
+    }
+
+    #[test]
+    fn added_types_extend_the_table() {
+        let mut table = TypeTable::with_built_ins();
+        table.add("web", vec![Glob::new("*.{html,css}").unwrap()]);
+        table.add("rust", vec![Glob::new("*.rs.in").unwrap()]);
+
+        let web = TypeFilter::new(&table, &names(&["web"]), &[]).unwrap();
+        assert!(web.allows("site/index.html"));
+        assert!(web.allows("site/style.css"));
+        assert!(!web.allows("site/app.js"));
+
+        let rust = TypeFilter::new(&table, &names(&["rust"]), &[]).unwrap();
+        assert!(rust.allows("build.rs") && rust.allows("template.rs.in"));
+    }
+
+    #[test]
+    fn lists_types_sorted() {
+        let mut table = TypeTable::with_built_ins();
+        table.add("web", vec![Glob::new("*.{html,css}").unwrap()]);
+        let listed = table.list();
+
+        assert!(listed.starts_with("c: *.c, *.h\n"));
+        assert!(listed.contains("rust: *.rs\n"));
+        assert!(listed.ends_with("web: *.{html,css}\nyaml: *.yaml, *.yml\n"));
+    }
+}
@@ -0,0 +1,206 @@
+// Following a growing file (--follow)
+
+// Like `tail -f`: when we reach the end of the file we don't stop, we wait a
+// little and look again. `Follower` does that inside `Read::read`, so to the
+// rest of minigrep a followed file is just a reader that never ends and
+// `search_stream` prints new matches as soon as their line is complete.
+
+// Log files don't only grow:
+// - truncation (`> app.log`, or logrotate's copytruncate): the file is now
+//   shorter than what we've read, so we start over from the beginning
+// - rotation (`mv app.log app.log.1` and a new app.log): the path now belongs
+//   to another file (another inode), so we finish the old file and reopen
+//   the path
+
+use std::{
+    fs::{ self, File, Metadata },
+    io::{ self, Read, Seek, SeekFrom },
+    path::PathBuf,
+    sync::{ Arc, atomic::{ AtomicBool, Ordering } },
+    thread,
+    time::Duration,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Follower {
+    path: PathBuf,
+    file: File,
+    id: u64,
+    position: u64,
+    stop: Arc<AtomicBool>,
+}
+
+// Something that tells two files apart even when they had the same path.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+// Without inodes we can't see a rotation, only a truncation.
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> u64 {
+    0
+}
+
+impl Follower {
+    pub fn open(path: &str) -> io::Result<Follower> {
+        let file = File::open(path)?;
+        let id = file_id(&file.metadata()?);
+
+        Ok(Follower {
+            path: PathBuf::from(path),
+            file,
+            id,
+            position: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    // Setting the flag makes the next wait end the input instead.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    // Called at the end of the current file: checks what happened to the path.
+    fn reopen_if_replaced(&mut self) -> io::Result<()> {
+        // while the path is missing (between `mv` and the creation of the new
+        // file) there is nothing to do but wait
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return Ok(());
+        };
+
+        if file_id(&metadata) != self.id {
+            self.file = File::open(&self.path)?;
+            self.id = file_id(&self.file.metadata()?);
+            self.position = 0;
+        } else if metadata.len() < self.position {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.position = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for Follower {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n > 0 {
+                self.position += n as u64;
+                return Ok(n);
+            }
+
+            // returning 0 means "end of input" to the caller
+            if self.stop.load(Ordering::SeqCst) {
+                return Ok(0);
+            }
+
+            self.reopen_if_replaced()?;
+            if self.position == 0 && self.file.metadata()?.len() > 0 {
+                // a new or truncated file with data in it, read it right away
+                continue;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ env, io::{ BufRead, BufReader, Write }, path::Path, sync::mpsc };
+
+    fn temp_log(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn append(path: &Path, text: &str) {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    // Reads lines from a follower on another thread and sends them back.
+    fn follow_lines(path: &Path) -> (mpsc::Receiver<String>, Arc<AtomicBool>, thread::JoinHandle<()>) {
+        let follower = Follower::open(path.to_str().unwrap()).unwrap();
+        let stop = follower.stop_handle();
+        let (tx, rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            for line in BufReader::new(follower).lines() {
+                tx.send(line.unwrap()).unwrap();
+            }
+        });
+
+        (rx, stop, handle)
+    }
+
+    fn next(lines: &mpsc::Receiver<String>) -> String {
+        lines.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn sees_appended_lines() {
+        let path = temp_log("append.log", "one\n");
+        let (lines, stop, handle) = follow_lines(&path);
+        assert_eq!("one", next(&lines));
+
+        let writer_path = path.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            append(&writer_path, "two\n");
+            thread::sleep(Duration::from_millis(150));
+            // a line written in two parts still comes out as one line
+            append(&writer_path, "thr");
+            thread::sleep(Duration::from_millis(150));
+            append(&writer_path, "ee\n");
+        });
+
+        assert_eq!("two", next(&lines));
+        assert_eq!("three", next(&lines));
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn starts_over_after_truncation() {
+        let path = temp_log("truncate.log", "a long first line\n");
+        let (lines, stop, handle) = follow_lines(&path);
+        assert_eq!("a long first line", next(&lines));
+
+        thread::sleep(Duration::from_millis(150));
+        fs::write(&path, "new\n").unwrap();
+
+        assert_eq!("new", next(&lines));
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reopens_after_rotation() {
+        let path = temp_log("rotate.log", "before\n");
+        let (lines, stop, handle) = follow_lines(&path);
+        assert_eq!("before", next(&lines));
+
+        thread::sleep(Duration::from_millis(150));
+        append(&path, "last words\n");
+        fs::rename(&path, path.with_extension("log.1")).unwrap();
+        fs::write(&path, "after\n").unwrap();
+
+        // the old file is finished first, then the new one is picked up
+        assert_eq!("last words", next(&lines));
+        assert_eq!("after", next(&lines));
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+// Glob patterns, for picking files by name
+
+//   *      any run of chars, but not across a '/'
+//   **     any run of chars, '/' included
+//   ?      exactly one char (not '/')
+//   {a,b}  either alternative, e.g. *.{html,css}
+//
+// Braces are expanded once when the glob is made ("*.{html,css}" becomes
+// "*.html" and "*.css"), so matching only has to deal with the wildcards.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    source: String,
+    alternatives: Vec<Vec<char>>,
+}
+
+impl Glob {
+    pub fn new(source: &str) -> Result<Glob, String> {
+        let alternatives = expand_braces(source)?
+            .into_iter()
+            .map(|alternative| alternative.chars().collect())
+            .collect();
+
+        Ok(Glob { source: source.to_string(), alternatives })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.alternatives.iter().any(|pattern| wildcard_match(pattern, &text))
+    }
+
+    // Whether a file at `path` (relative, '/'-separated) is covered by the
+    // glob, the way .gitignore lines work: a glob without a '/' is about names
+    // and matches any part of the path ("target" takes everything under every
+    // target directory), one with a '/' matches the path from the top
+    // ("docs/build" takes everything under docs/build).
+    pub fn covers(&self, path: &str) -> bool {
+        if !self.source.contains('/') {
+            return path.split('/').any(|part| self.is_match(part));
+        }
+
+        let mut prefix = String::new();
+        for part in path.split('/') {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            if self.is_match(&prefix) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Splits "*.rs, *.{html,css}" at the commas that aren't inside braces.
+pub fn split_list(list: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in list.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(list[start..].trim());
+
+    parts
+}
+
+fn expand_braces(source: &str) -> Result<Vec<String>, String> {
+    let Some(open) = source.find('{') else {
+        if source.contains('}') {
+            return Err(format!("unmatched '}}' in {source}"));
+        }
+        return Ok(vec![source.to_string()]);
+    };
+
+    // find the matching '}' and the top level commas in between
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut close = None;
+    for (i, c) in source[open..].char_indices().map(|(i, c)| (open + i, c)) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            ',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+    }
+    let Some(close) = close else {
+        return Err(format!("unmatched '{{' in {source}"));
+    };
+
+    let (before, after) = (&source[..open], &source[close + 1..]);
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+
+    // every alternative may contain braces itself, and so may the rest
+    let mut expanded = Vec::new();
+    for window in bounds.windows(2) {
+        let alternative = &source[window[0] + 1..window[1]];
+        expanded.extend(expand_braces(&format!("{before}{alternative}{after}"))?);
+    }
+    Ok(expanded)
+}
+
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|skip| wildcard_match(rest, &text[skip..])),
+        ['*', rest @ ..] => {
+            // as far as the next '/' at most
+            let reach = text
+                .iter()
+                .position(|&c| c == '/')
+                .unwrap_or(text.len());
+            (0..=reach).any(|skip| wildcard_match(rest, &text[skip..]))
+        }
+        ['?', rest @ ..] => matches!(text, [c, ..] if *c != '/') && wildcard_match(rest, &text[1..]),
+        [p, rest @ ..] => text.first() == Some(p) && wildcard_match(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stars_and_question_marks() {
+        let glob = Glob::new("*.rs").unwrap();
+        assert!(glob.is_match("main.rs"));
+        assert!(glob.is_match(".rs"));
+        assert!(!glob.is_match("src/main.rs"));
+        assert!(!glob.is_match("main.rsx"));
+
+        assert!(Glob::new("ch?.md").unwrap().is_match("ch8.md"));
+        assert!(!Glob::new("ch?.md").unwrap().is_match("ch12.md"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        let glob = Glob::new("src/**/*.rs").unwrap();
+
+        assert!(glob.is_match("src/books/ch12_grep/mod.rs"));
+        assert!(!glob.is_match("tests/mod.rs"));
+    }
+
+    #[test]
+    fn braces_are_alternatives() {
+        let glob = Glob::new("*.{html,css,{ts,js}x}").unwrap();
+
+        for name in ["index.html", "site.css", "app.tsx", "app.jsx"] {
+            assert!(glob.is_match(name), "{name}");
+        }
+        assert!(!glob.is_match("app.ts"));
+        assert!(Glob::new("*.{html").is_err());
+    }
+
+    #[test]
+    fn lists_split_outside_braces() {
+        assert_eq!(vec!["*.rs", "*.{html,css}", "Makefile"], split_list("*.rs, *.{html,css},Makefile"));
+    }
+
+    #[test]
+    fn covers_paths_like_gitignore() {
+        let by_name = Glob::new("target").unwrap();
+        assert!(by_name.covers("target/debug/minigrep"));
+        assert!(by_name.covers("nested/target/x"));
+        assert!(!by_name.covers("targets.txt"));
+
+        let by_path = Glob::new("docs/build").unwrap();
+        assert!(by_path.covers("docs/build/index.html"));
+        assert!(!by_path.covers("src/docs/build/index.html"));
+    }
+}
@@ -0,0 +1,502 @@
+// A trigram index for searching the same tree over and over
+
+// `minigrep index build <dir>` reads every file under `dir` once and records
+// which 3-byte sequences ("trigrams") appear in it. The result is an inverted
+// index: for every trigram, the list of files containing it.
+//
+// A file can only contain "banish" if it contains all of "ban", "ani", "nis"
+// and "ish", so `minigrep --index <dir> banish` intersects those four lists
+// and only searches the files left over. The normal search still decides
+// what matches, the index only rules out files that can't; that's why results
+// are the same as searching every file.
+//
+// Trigrams are recorded from ASCII-lowercased bytes, so one index serves both
+// case-sensitive and IGNORE_CASE searches.
+//
+// Every entry keeps the file's modification time and size. A file that
+// changed since the index was built is always searched, and building again
+// only re-reads the files that changed.
+
+use std::{
+    collections::{ HashMap, HashSet },
+    fs::{ self, File },
+    io::{ self, BufReader, BufWriter, Read, Write },
+    path::{ Path, PathBuf },
+    time::UNIX_EPOCH,
+};
+
+use crate::temp_file;
+
+use super::decompress;
+
+pub const INDEX_FILE: &str = ".minigrep-index";
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+type Trigram = [u8; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileEntry {
+    // relative to the indexed directory, with '/' separators
+    path: String,
+    modified: (u64, u32),
+    len: u64,
+    // compressed or not UTF-8: what gets searched isn't these bytes, so the
+    // trigrams can't be trusted and the file is always searched
+    opaque: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    files: Vec<FileEntry>,
+    // trigram -> ids (positions in `files`) of the files containing it, sorted
+    postings: HashMap<Trigram, Vec<u32>>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BuildStats {
+    pub reused: usize,
+    pub read: usize,
+    pub removed: usize,
+}
+
+// Every regular file under `dir`, relative to it and sorted. Hidden files and
+// directories (like .git, or the index itself) are skipped, and so are
+// symlinks.
+pub fn walk(dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let file_type = entry.file_type()?;
+            let path = relative.join(&name);
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                files.push(to_slashes(&path));
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn to_slashes(path: &Path) -> String {
+    path.components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn stamp(metadata: &fs::Metadata) -> io::Result<((u64, u32), u64)> {
+    let since_epoch = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Ok(((since_epoch.as_secs(), since_epoch.subsec_nanos()), metadata.len()))
+}
+
+fn trigrams(bytes: &[u8]) -> HashSet<Trigram> {
+    bytes
+        .windows(3)
+        .map(|w| [w[0].to_ascii_lowercase(), w[1].to_ascii_lowercase(), w[2].to_ascii_lowercase()])
+        .collect()
+}
+
+// The trigrams a line matching `pattern` must contain. With IGNORE_CASE the
+// pattern is already lowercased, and a few trigrams have to be left out:
+// non-ASCII letters have upper case forms we didn't lowercase when indexing,
+// and the Kelvin sign 'K' and 'İ' lowercase to an ASCII 'k' and 'i'.
+fn pattern_trigrams(pattern: &str, ignore_case: bool) -> Vec<Trigram> {
+    trigrams(pattern.as_bytes())
+        .into_iter()
+        .filter(|trigram| {
+            !ignore_case || trigram.iter().all(|&b| b.is_ascii() && b != b'k' && b != b'i')
+        })
+        .collect()
+}
+
+impl Index {
+    // Builds the index for `dir`, reusing everything from `previous` whose
+    // file hasn't changed since.
+    pub fn build(dir: &Path, previous: Option<&Index>) -> io::Result<(Index, BuildStats)> {
+        let mut index = Index::default();
+        let mut stats = BuildStats::default();
+
+        let old_files: HashMap<&str, (u32, &FileEntry)> = previous
+            .map(|previous| {
+                previous.files
+                    .iter()
+                    .enumerate()
+                    .map(|(id, entry)| (entry.path.as_str(), (id as u32, entry)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        // old id -> new id for every file whose entry is reused
+        let mut reused_ids: HashMap<u32, u32> = HashMap::new();
+
+        for path in walk(dir)? {
+            let (modified, len) = stamp(&fs::metadata(dir.join(&path))?)?;
+            let id = index.files.len() as u32;
+
+            if
+                let Some(&(old_id, old)) = old_files.get(path.as_str()) &&
+                old.modified == modified &&
+                old.len == len
+            {
+                reused_ids.insert(old_id, id);
+                index.files.push(old.clone());
+                stats.reused += 1;
+                continue;
+            }
+
+            let bytes = fs::read(dir.join(&path))?;
+            let opaque =
+                decompress::detect(&bytes) != decompress::Compression::None ||
+                str::from_utf8(&bytes).is_err();
+            if !opaque {
+                for trigram in trigrams(&bytes) {
+                    index.postings.entry(trigram).or_default().push(id);
+                }
+            }
+
+            index.files.push(FileEntry { path, modified, len, opaque });
+            stats.read += 1;
+        }
+
+        // carry over the postings of the reused files under their new ids
+        if let Some(previous) = previous {
+            // files that are gone; changed ones were counted as read
+            let walked: HashSet<&str> = index.files.iter().map(|entry| entry.path.as_str()).collect();
+            stats.removed = previous.files
+                .iter()
+                .filter(|entry| !walked.contains(entry.path.as_str()))
+                .count();
+
+            for (trigram, ids) in &previous.postings {
+                let carried: Vec<u32> = ids
+                    .iter()
+                    .filter_map(|old_id| reused_ids.get(old_id).copied())
+                    .collect();
+                if !carried.is_empty() {
+                    index.postings.entry(*trigram).or_default().extend(carried);
+                }
+            }
+        }
+
+        for ids in index.postings.values_mut() {
+            ids.sort_unstable();
+        }
+
+        Ok((index, stats))
+    }
+
+    // The files under `dir` that may match any of `patterns`, as paths
+    // relative to `dir`. `use_trigrams` is false for searches the trigrams
+    // can't help with (like --fuzzy), then every file is a candidate.
+    pub fn candidates(
+        &self,
+        dir: &Path,
+        patterns: &[String],
+        ignore_case: bool,
+        use_trigrams: bool
+    ) -> io::Result<Vec<String>> {
+        let possible = if use_trigrams { self.files_matching_any(patterns, ignore_case) } else { None };
+
+        let by_path: HashMap<&str, (u32, &FileEntry)> = self.files
+            .iter()
+            .enumerate()
+            .map(|(id, entry)| (entry.path.as_str(), (id as u32, entry)))
+            .collect();
+
+        let mut candidates = Vec::new();
+        for path in walk(dir)? {
+            let keep = match by_path.get(path.as_str()) {
+                Some(&(id, entry)) => {
+                    let fresh = stamp(&fs::metadata(dir.join(&path))?)? == (entry.modified, entry.len);
+
+                    // new, changed and opaque files are always searched
+                    !fresh || entry.opaque || possible.as_ref().is_none_or(|ids| ids.contains(&id))
+                }
+                None => true,
+            };
+
+            if keep {
+                candidates.push(path);
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    // None means "can't tell, any file".
+    fn files_matching_any(&self, patterns: &[String], ignore_case: bool) -> Option<HashSet<u32>> {
+        let mut possible = HashSet::new();
+
+        for pattern in patterns {
+            let needed = pattern_trigrams(pattern, ignore_case);
+            if needed.is_empty() {
+                // too short (or nothing usable) to rule anything out
+                return None;
+            }
+
+            let mut lists: Vec<&Vec<u32>> = Vec::new();
+            for trigram in &needed {
+                match self.postings.get(trigram) {
+                    Some(ids) => lists.push(ids),
+                    // no file has this trigram, so no file has this pattern
+                    None => {
+                        lists.clear();
+                        break;
+                    }
+                }
+            }
+            if lists.is_empty() {
+                continue;
+            }
+
+            // start from the shortest list, it bounds the intersection
+            lists.sort_by_key(|ids| ids.len());
+            for &id in lists[0] {
+                if lists[1..].iter().all(|ids| ids.binary_search(&id).is_ok()) {
+                    possible.insert(id);
+                }
+            }
+        }
+
+        Some(possible)
+    }
+
+    pub fn load(dir: &Path) -> io::Result<Index> {
+        let mut reader = BufReader::new(File::open(dir.join(INDEX_FILE))?);
+
+        let mut magic = vec![0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a minigrep index"));
+        }
+
+        let mut index = Index::default();
+
+        for _ in 0..read_u32(&mut reader)? {
+            let path_len = read_u32(&mut reader)? as usize;
+            let mut path = vec![0; path_len];
+            reader.read_exact(&mut path)?;
+            let path = String::from_utf8(path).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, e)
+            })?;
+
+            let secs = read_u64(&mut reader)?;
+            let nanos = read_u32(&mut reader)?;
+            let len = read_u64(&mut reader)?;
+            let mut opaque = [0];
+            reader.read_exact(&mut opaque)?;
+
+            index.files.push(FileEntry { path, modified: (secs, nanos), len, opaque: opaque[0] == 1 });
+        }
+
+        for _ in 0..read_u32(&mut reader)? {
+            let mut trigram = [0; 3];
+            reader.read_exact(&mut trigram)?;
+            let count = read_u32(&mut reader)?;
+            let mut ids = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                ids.push(read_u32(&mut reader)?);
+            }
+            index.postings.insert(trigram, ids);
+        }
+
+        Ok(index)
+    }
+
+    // Written next to the index and renamed over it, so a search running at
+    // the same time never sees half an index, and two builds at the same time
+    // don't write into each other's temporary file.
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let target = dir.join(INDEX_FILE);
+        let (temp, file) = temp_file::create_next_to(&target)?;
+
+        let result = self.write_to(file).and_then(|()| fs::rename(&temp, &target));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    fn write_to(&self, file: File) -> io::Result<()> {
+        let mut writer = BufWriter::new(file);
+
+        writer.write_all(MAGIC)?;
+
+        writer.write_all(&(self.files.len() as u32).to_le_bytes())?;
+        for entry in &self.files {
+            writer.write_all(&(entry.path.len() as u32).to_le_bytes())?;
+            writer.write_all(entry.path.as_bytes())?;
+            writer.write_all(&entry.modified.0.to_le_bytes())?;
+            writer.write_all(&entry.modified.1.to_le_bytes())?;
+            writer.write_all(&entry.len.to_le_bytes())?;
+            writer.write_all(&[u8::from(entry.opaque)])?;
+        }
+
+        // sorted, so the same tree always gives the same file
+        let mut trigrams: Vec<&Trigram> = self.postings.keys().collect();
+        trigrams.sort();
+
+        writer.write_all(&(trigrams.len() as u32).to_le_bytes())?;
+        for trigram in trigrams {
+            let ids = &self.postings[trigram];
+            writer.write_all(trigram)?;
+            writer.write_all(&(ids.len() as u32).to_le_bytes())?;
+            for id in ids {
+                writer.write_all(&id.to_le_bytes())?;
+            }
+        }
+
+        writer.into_inner()?.sync_all()
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn corpus(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-index-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("docs/nested")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+
+        fs::write(dir.join("poem.txt"), "I'm nobody! Who are you?\nThey'd banish us, you know.\n").unwrap();
+        fs::write(dir.join("docs/rust.md"), "Rust:\nsafe, fast, productive.\nPick three.\n").unwrap();
+        fs::write(dir.join("docs/nested/notes.txt"), "Trust me.\n").unwrap();
+        fs::write(dir.join(".git/HEAD"), "banish\n").unwrap();
+        dir
+    }
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn walk_skips_hidden_entries() {
+        let dir = corpus("walk");
+
+        assert_eq!(strings(&["docs/nested/notes.txt", "docs/rust.md", "poem.txt"]), walk(&dir).unwrap());
+    }
+
+    #[test]
+    fn picks_only_files_with_every_trigram() {
+        let dir = corpus("pick");
+        let (index, _) = Index::build(&dir, None).unwrap();
+
+        let found = index.candidates(&dir, &strings(&["banish"]), false, true).unwrap();
+        assert_eq!(strings(&["poem.txt"]), found);
+
+        // ASCII case doesn't matter to the index
+        let found = index.candidates(&dir, &strings(&["rust"]), true, true).unwrap();
+        assert_eq!(strings(&["docs/nested/notes.txt", "docs/rust.md"]), found);
+
+        let found = index.candidates(&dir, &strings(&["nowhere"]), false, true).unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn short_patterns_and_fuzzy_search_every_file() {
+        let dir = corpus("short");
+        let (index, _) = Index::build(&dir, None).unwrap();
+
+        assert_eq!(3, index.candidates(&dir, &strings(&["me"]), false, true).unwrap().len());
+        assert_eq!(3, index.candidates(&dir, &strings(&["banish"]), false, false).unwrap().len());
+    }
+
+    #[test]
+    fn any_pattern_can_select_a_file() {
+        let dir = corpus("any");
+        let (index, _) = Index::build(&dir, None).unwrap();
+
+        let found = index.candidates(&dir, &strings(&["banish", "three"]), false, true).unwrap();
+
+        assert_eq!(strings(&["docs/rust.md", "poem.txt"]), found);
+    }
+
+    #[test]
+    fn saved_index_loads_back() {
+        let dir = corpus("save");
+        let (index, _) = Index::build(&dir, None).unwrap();
+
+        index.save(&dir).unwrap();
+
+        assert_eq!(index, Index::load(&dir).unwrap());
+    }
+
+    #[test]
+    fn saves_at_the_same_time_dont_share_a_temp_file() {
+        let dir = corpus("save-race");
+        let (index, _) = Index::build(&dir, None).unwrap();
+        let before: usize = fs::read_dir(&dir).unwrap().count();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| index.save(&dir).unwrap());
+            }
+        });
+
+        assert_eq!(index, Index::load(&dir).unwrap());
+        // the index itself and nothing else
+        assert_eq!(before + 1, fs::read_dir(&dir).unwrap().count());
+    }
+
+    #[test]
+    fn changed_and_new_files_are_always_candidates() {
+        let dir = corpus("stale");
+        let (index, _) = Index::build(&dir, None).unwrap();
+
+        fs::write(dir.join("docs/rust.md"), "now it says banish, and is longer\n").unwrap();
+        fs::write(dir.join("new.txt"), "banish\n").unwrap();
+
+        let found = index.candidates(&dir, &strings(&["banish"]), false, true).unwrap();
+
+        assert_eq!(strings(&["docs/rust.md", "new.txt", "poem.txt"]), found);
+    }
+
+    #[test]
+    fn rebuild_only_reads_changed_files() {
+        let dir = corpus("incremental");
+        let (first, stats) = Index::build(&dir, None).unwrap();
+        assert_eq!(BuildStats { reused: 0, read: 3, removed: 0 }, stats);
+
+        fs::write(dir.join("docs/rust.md"), "banish the rust\n").unwrap();
+        fs::remove_file(dir.join("docs/nested/notes.txt")).unwrap();
+        fs::write(dir.join("added.txt"), "three\n").unwrap();
+
+        let (second, stats) = Index::build(&dir, Some(&first)).unwrap();
+        assert_eq!(BuildStats { reused: 1, read: 2, removed: 1 }, stats);
+
+        // same result as indexing from scratch
+        let (fresh, _) = Index::build(&dir, None).unwrap();
+        assert_eq!(fresh, second);
+    }
+}
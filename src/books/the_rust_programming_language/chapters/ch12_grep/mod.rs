@@ -1,8 +1,52 @@
-use std::{ env, error::Error, fs, process };
+mod aho_corasick;
+mod binary;
+mod config_file;
+mod decompress;
+mod encoding;
+mod file_types;
+mod follow;
+mod glob;
+mod index;
+mod parallel;
+mod replace;
+
+use std::{
+    borrow::Cow,
+    env,
+    error::Error,
+    fs,
+    io::{ self, BufRead, IsTerminal, Read, Write },
+    path::Path,
+    process,
+    str,
+};
+
+use crate::report::{ Context, report };
+
+use aho_corasick::{ PatternMatch, PatternSet };
+use binary::BinaryFiles;
+use encoding::Encoding;
+use file_types::{ TypeFilter, TypeTable };
+use glob::Glob;
 
 pub fn main() {
     let args: Vec<String> = env::args().collect();
 
+    // `minigrep index build <dir>` isn't a search, it prepares one
+    if is_index_command(&args) {
+        if let Err(e) = run_index_command(&args[3..]) {
+            // println!("Application error: {e}");
+            println!("{}", report(e.as_ref()));
+            process::exit(1);
+        }
+        return;
+    }
+
+    let args = with_config_file(args).unwrap_or_else(|err| {
+        println!("Problem reading the config file: {err}");
+        process::exit(1)
+    });
+
     // let config = parse_config(&args);
     // let config = Config::new(&args);
     let config = Config::build(args.into_iter()).unwrap_or_else(|err| {
@@ -10,8 +54,27 @@ pub fn main() {
         process::exit(1)
     });
 
-    println!("Searching for {}", config.query);
-    println!("In file {}", config.file_path);
+    if config.type_list {
+        print!("{}", config.types.list());
+        return;
+    }
+
+    // keep stdout clean when it's meant to be read by another program: JSON,
+    // the replaced text, or a --dry-run diff for `patch` or `git apply`
+    if !config.json && config.replace.is_none() {
+        for query in &config.patterns {
+            println!("Searching for {query}");
+        }
+        for pattern_file in &config.pattern_files {
+            println!("Searching for patterns listed in {pattern_file}");
+        }
+        match (&config.index_dir, &config.file_path) {
+            (Some(index_dir), _) => println!("In the files indexed in {index_dir}"),
+            (None, Some(file_path)) if config.recursive => println!("In directory {file_path}"),
+            (None, Some(file_path)) => println!("In file {file_path}"),
+            (None, None) => println!("In standard input"),
+        }
+    }
 
     // let contents = fs
     //     ::read_to_string(config.file_path)
@@ -21,11 +84,26 @@ pub fn main() {
     // run(config);
 
     if let Err(e) = run(config) {
-        println!("Application error: {e}");
+        // println!("Application error: {e}");
+
+        // the error and everything that caused it, one per line
+        println!("{}", report(e.as_ref()));
         process::exit(1);
     }
 }
 
+// Puts the options from the config file right after the program name, so the
+// ones given on the command line come later and win.
+fn with_config_file(mut args: Vec<String>) -> Result<Vec<String>, Box<dyn Error>> {
+    if args.iter().any(|arg| arg == "--no-config") {
+        return Ok(args);
+    }
+
+    let defaults = config_file::load()?;
+    args.splice(1..1, defaults);
+    Ok(args)
+}
+
 fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     // let mut results = Vec::new();
 
@@ -56,10 +134,319 @@ pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a st
     results
 }
 
+// A line containing a substring within `max_distance` edits of the query.
+// `start..end` are byte offsets into `line`, so `&line[start..end]` is the
+// closest substring that was found.
+#[derive(Debug, PartialEq)]
+pub struct FuzzyHit<'a> {
+    pub line_number: usize,
+    pub line: &'a str,
+    pub distance: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+// Approximate version of `search`: a line matches when some substring of it
+// can be turned into the query with at most `max_distance` insertions,
+// deletions or substitutions (Levenshtein distance).
+pub fn search_fuzzy<'a>(query: &str, max_distance: usize, contents: &'a str) -> Vec<FuzzyHit<'a>> {
+    fuzzy_lines(query, max_distance, contents, false)
+}
+
+pub fn search_fuzzy_case_insensitive<'a>(
+    query: &str,
+    max_distance: usize,
+    contents: &'a str
+) -> Vec<FuzzyHit<'a>> {
+    fuzzy_lines(query, max_distance, contents, true)
+}
+
+fn fuzzy_lines<'a>(
+    query: &str,
+    max_distance: usize,
+    contents: &'a str,
+    ignore_case: bool
+) -> Vec<FuzzyHit<'a>> {
+    let query: Vec<char> = query.chars().collect();
+
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| fuzzy_hit(&query, max_distance, ignore_case, index + 1, line))
+        .collect()
+}
+
+fn fuzzy_hit<'a>(
+    query: &[char],
+    max_distance: usize,
+    ignore_case: bool,
+    line_number: usize,
+    line: &'a str
+) -> Option<FuzzyHit<'a>> {
+    // with IGNORE_CASE, compare char by char instead of lowercasing the whole
+    // line, so the reported span still points into the original line
+    let (distance, start, end) = if ignore_case {
+        best_substring(query, line, |a, b| a.to_lowercase().eq(b.to_lowercase()))
+    } else {
+        best_substring(query, line, |a, b| a == b)
+    };
+
+    if distance > max_distance {
+        return None;
+    }
+
+    Some(FuzzyHit {
+        line_number,
+        line,
+        distance,
+        start,
+        end,
+    })
+}
+
+// Sellers' algorithm: the usual Levenshtein table, except that the first row
+// is all zeros, so a match may start anywhere in the line for free. Every
+// cell also remembers where in the line its alignment started, which gives
+// back the span of the best match.
+//
+// Returns (distance, start byte, end byte) of the closest substring.
+fn best_substring(query: &[char], line: &str, same: impl Fn(char, char) -> bool) -> (usize, usize, usize) {
+    // byte offset of every char boundary, including the end of the line
+    let mut offsets: Vec<usize> = line
+        .char_indices()
+        .map(|(i, _)| i)
+        .collect();
+    offsets.push(line.len());
+    let text: Vec<char> = line.chars().collect();
+
+    // column for "no text consumed yet": matching the first i query chars
+    // against nothing takes i deletions
+    let mut previous: Vec<(usize, usize)> = (0..=query.len()).map(|i| (i, 0)).collect();
+    let mut best = (previous[query.len()].0, 0, 0);
+
+    for (j, &c) in text.iter().enumerate() {
+        let mut current = vec![(0, j + 1)];
+
+        for (i, &q) in query.iter().enumerate() {
+            let substitute = (previous[i].0 + usize::from(!same(q, c)), previous[i].1);
+            let delete = (current[i].0 + 1, current[i].1);
+            let insert = (previous[i + 1].0 + 1, previous[i + 1].1);
+
+            // cheapest first; on a tie `min_by_key` keeps the first one, so a
+            // substitution wins over skipping a char ("jello" rather than
+            // "ello" for "hello")
+            let cell = [substitute, delete, insert]
+                .into_iter()
+                .min_by_key(|&(distance, _)| distance)
+                .unwrap();
+            current.push(cell);
+        }
+
+        let (distance, start) = current[query.len()];
+        // for the same reason, an equally good span that just extends the
+        // best one by a char is preferred ("connectiom" over "connectio")
+        let extends_best = distance == best.0 && start == best.1 && best.2 == j;
+        if distance < best.0 || extends_best {
+            best = (distance, start, j + 1);
+        }
+        previous = current;
+    }
+
+    (best.0, offsets[best.1], offsets[best.2])
+}
+
+// A line that matched at least one pattern, with the index of every pattern
+// that matched it (in order of first appearance).
+#[derive(Debug, PartialEq)]
+pub struct PatternHit<'a> {
+    pub line_number: usize,
+    pub line: &'a str,
+    pub patterns: Vec<usize>,
+}
+
+// How much of the line a pattern occurrence has to cover to count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    // anywhere in the line, like `line.contains(query)`
+    Substring,
+    // -w: not glued to another word character on either side
+    Word,
+    // -x: the occurrence is the whole line
+    Line,
+}
+
+impl MatchMode {
+    fn accepts(self, line: &str, start: usize, end: usize) -> bool {
+        match self {
+            MatchMode::Substring => true,
+            MatchMode::Word => {
+                // look at whole chars around the match rather than single
+                // bytes (as `first_word` in ch4 does with `b' '`), so "é" or
+                // "д" next to the match count as part of the word
+                let before = line[..start].chars().next_back();
+                let after = line[end..].chars().next();
+                !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+            }
+            MatchMode::Line => start == 0 && end == line.len(),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Same idea as `search`, but for any number of patterns at once: the pattern
+// set is an Aho-Corasick automaton, so each line is scanned a single time no
+// matter how many patterns there are.
+pub fn search_patterns<'a>(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    contents: &'a str
+) -> Vec<PatternHit<'a>> {
+    search_lines(patterns, mode, contents, false)
+}
+
+// Like `search_case_insensitive`, the line is lowercased before matching, so
+// the pattern set must have been built from lowercased patterns. Word and
+// line checks then run on the lowercased line.
+pub fn search_patterns_case_insensitive<'a>(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    contents: &'a str
+) -> Vec<PatternHit<'a>> {
+    search_lines(patterns, mode, contents, true)
+}
+
+fn search_lines<'a>(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    contents: &'a str,
+    ignore_case: bool
+) -> Vec<PatternHit<'a>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| pattern_hit(patterns, mode, ignore_case, index + 1, line))
+        .collect()
+}
+
+fn pattern_hit<'a>(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    ignore_case: bool,
+    line_number: usize,
+    line: &'a str
+) -> Option<PatternHit<'a>> {
+    let mut matched = Vec::new();
+    for found in find_in_line(patterns, mode, line, ignore_case) {
+        if !matched.contains(&found.pattern) {
+            matched.push(found.pattern);
+        }
+    }
+
+    if matched.is_empty() {
+        return None;
+    }
+
+    Some(PatternHit {
+        line_number,
+        line,
+        patterns: matched,
+    })
+}
+
+// Every occurrence the match mode accepts, with `start..end` as byte offsets
+// into the original `line` even when it was lowercased for matching.
+fn find_in_line(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    line: &str,
+    ignore_case: bool
+) -> Vec<PatternMatch> {
+    if !ignore_case {
+        return patterns
+            .find_iter(line)
+            .into_iter()
+            .filter(|found| mode.accepts(line, found.start, found.end))
+            .collect();
+    }
+
+    let (folded, offsets) = fold_case(line);
+    patterns
+        .find_iter(&folded)
+        .into_iter()
+        .filter(|found| mode.accepts(&folded, found.start, found.end))
+        .map(|found| PatternMatch {
+            pattern: found.pattern,
+            start: offsets[found.start],
+            end: offsets[found.end],
+        })
+        .collect()
+}
+
+// Picks the matches that --replace and -o work on: leftmost first, and the longest one when
+// several patterns start at the same place. Overlapping matches are skipped.
+fn leftmost_non_overlapping(mut matches: Vec<PatternMatch>) -> Vec<PatternMatch> {
+    matches.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+
+    let mut chosen: Vec<PatternMatch> = Vec::new();
+    for found in matches {
+        if chosen.last().is_none_or(|last| found.start >= last.end) {
+            chosen.push(found);
+        }
+    }
+    chosen
+}
+
+// Lowercases `line` and also returns, for every byte of the result (plus one
+// past the end), the byte offset in `line` it came from. Lowercasing can
+// change the length of a char ('İ' becomes two chars), so offsets into the
+// lowercased line can't be used on the original directly.
+fn fold_case(line: &str) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(line.len());
+    let mut offsets = Vec::with_capacity(line.len() + 1);
+
+    for (i, c) in line.char_indices() {
+        for lower in c.to_lowercase() {
+            folded.push(lower);
+            offsets.extend(std::iter::repeat_n(i, lower.len_utf8()));
+        }
+    }
+    offsets.push(line.len());
+
+    (folded, offsets)
+}
+
+// A file read into memory, decompressed if needed.
+struct Input {
+    contents: String,
+    compression: decompress::Compression,
+    binary: bool,
+}
+
+fn read_input(path: &str, search_zip: bool) -> Result<Input, Box<dyn Error>> {
+    // with -z, gzip and zstd files are decompressed on the way in
+    let (mut reader, compression) = decompress::open(path, search_zip)?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let binary = binary::is_binary(&bytes);
+    let contents = if binary {
+        // binary data is hardly ever valid UTF-8, so `read_to_string` would
+        // just fail; replace the invalid bytes with '\u{FFFD}' instead
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        String::from_utf8(bytes)?
+    };
+
+    Ok(Input { contents, compression, binary })
+}
+
 fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs
-        ::read_to_string(config.file_path)
-        .expect("Should have been able to read the file");
+    // let contents = fs
+    //     ::read_to_string(&config.file_path)
+    //     .expect("Should have been able to read the file");
 
     // println!("With text:\n{contents}");
 
@@ -67,99 +454,2051 @@ fn run(config: Config) -> Result<(), Box<dyn Error>> {
     //     println!("{line}");
     // }
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+    // -e patterns first, then every line of every -f file
+    let mut patterns = config.patterns.clone();
+    for pattern_file in &config.pattern_files {
+        let listed = fs::read_to_string(pattern_file).with_context(|| format!("reading patterns from {pattern_file}"))?;
+        patterns.extend(listed.lines().map(String::from));
+    }
+
+    let set = if config.ignore_case {
+        PatternSet::new(
+            patterns
+                .iter()
+                .map(|p| p.to_lowercase())
+                .collect()
+        )
     } else {
-        search(&config.query, &contents)
+        PatternSet::new(patterns.clone())
     };
 
-    for line in results {
-        println!("{line}");
+    if let Some(index_dir) = &config.index_dir {
+        return run_indexed(&config, &set, &patterns, Path::new(index_dir));
     }
+    if let (true, Some(dir)) = (config.recursive, &config.file_path) {
+        return run_recursive(&config, &set, &patterns, Path::new(dir));
+    }
+
+    // rewriting a file (or diffing it) needs all of it at once
+    if config.in_place || config.dry_run {
+        return run_replace(&config, &set);
+    }
+
+    // everything else works one line at a time, so a file and a pipe like
+    // `tail -f app.log | minigrep ERROR` go through the same code
+    let (reader, _) = match &config.file_path {
+        // --follow never reaches the end of the file, it waits for more
+        Some(path) if config.follow => {
+            let follower = follow::Follower::open(path).with_context(|| format!("following {path}"))?;
+            decompress::wrap(follower, config.search_zip)?
+        }
+        Some(path) => decompress::open(path, config.search_zip).with_context(|| format!("opening {path}"))?,
+        None => decompress::wrap(io::stdin(), config.search_zip)?,
+    };
+
+    search_stream(&config, &set, &patterns, reader, &mut io::stdout().lock())
+}
+
+// Only `index build`, so `minigrep index notes.txt` still searches for "index".
+fn is_index_command(args: &[String]) -> bool {
+    matches!(args, [_, command, action, ..] if command == "index" && action == "build")
+}
+
+fn run_index_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [dir] = args else {
+        return Err("usage: minigrep index build <dir>".into());
+    };
+
+    let dir = Path::new(dir);
+    // an old index only saves work; if it's missing or unreadable we start over
+    let previous = index::Index::load(dir).ok();
+    let (built, stats) = index::Index
+        ::build(dir, previous.as_ref())
+        .with_context(|| format!("indexing {}", dir.display()))?;
+    built.save(dir).with_context(|| format!("saving the index of {}", dir.display()))?;
+
+    println!(
+        "Indexed {} files in {} ({} read, {} unchanged, {} removed)",
+        stats.read + stats.reused,
+        dir.display(),
+        stats.read,
+        stats.reused,
+        stats.removed
+    );
 
     Ok(())
 }
 
-struct Config {
-    query: String,
-    file_path: String,
-    ignore_case: bool,
+// --index DIR: search only the files under DIR that the index can't rule out.
+// Each candidate goes through the same `search_stream` as a single file, with
+// its path in front of every match.
+fn run_indexed(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    dir: &Path
+) -> Result<(), Box<dyn Error>> {
+    let candidates = indexed_candidates(config, patterns, dir)?;
+
+    let mut out = io::stdout().lock();
+    search_files(config, set, patterns, dir, &candidates, &mut out)
 }
 
-// fn parse_config(args: &[String]) -> Config {
-//     let query = args[1].clone();
-//     let file_path = args[2].clone();
+// -r: every file under DIR, without an index.
+fn run_recursive(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    dir: &Path
+) -> Result<(), Box<dyn Error>> {
+    let mut files = index::walk(dir).with_context(|| format!("listing the files in {}", dir.display()))?;
+    files.retain(|file| wanted(config, file));
 
-//     Config { query, file_path }
-// }
+    let mut out = io::stdout().lock();
+    search_files(config, set, patterns, dir, &files, &mut out)
+}
 
-impl Config {
-    // fn new(args: &[String]) -> Config {
-    //     if args.len() < 3 {
-    //         panic!("not enough arguments");
-    //     }
-    //     let query = args[1].clone();
-    //     let file_path = args[2].clone();
+// Whether a file found in a directory search passes --ignore, -t and -T.
+fn wanted(config: &Config, file: &str) -> bool {
+    !config.ignore.iter().any(|glob| glob.covers(file)) && config.type_filter.allows(file)
+}
 
-    //     Config { query, file_path }
-    // }
+fn indexed_candidates(config: &Config, patterns: &[String], dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let index = index::Index::load(dir).map_err(|e| {
+        format!(
+            "couldn't read the index of {dir} ({e}), run `minigrep index build {dir}` first",
+            dir = dir.display()
+        )
+    })?;
 
-    // returning a result instead of calling panic
-    // fn build(args: &[String]) -> Result<Config, &'static str> {
-    //     // error values will always be string literals that have the 'static lifetime.
-    //     if args.len() < 3 {
-    //         return Err("not enough arguments");
-    //     }
+    // a fuzzy match doesn't have to contain any of the query's trigrams, and
+    // the trigrams of a file read as another encoding aren't the file's bytes
+    let use_trigrams = config.fuzzy.is_none() && config.encoding.is_none_or(|e| e == Encoding::Utf8);
+    let mut candidates = index.candidates(dir, patterns, config.ignore_case, use_trigrams)?;
+    candidates.retain(|file| wanted(config, file));
 
-    //     let query = args[1].clone();
-    //     let file_path = args[2].clone();
-    //     let ignore_case = env::var("IGNORE_CASE").is_ok();
+    Ok(candidates)
+}
 
-    //     Ok(Config { query, file_path, ignore_case })
-    // }
+// The files of a directory search (-r or --index): one after the other, on a
+// pool of threads with -j N, or as futures with --async.
+fn search_files(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    dir: &Path,
+    files: &[String],
+    out: &mut impl Write
+) -> Result<(), Box<dyn Error>> {
+    if config.async_search {
+        let max_open = config.jobs.unwrap_or(parallel::DEFAULT_OPEN_FILES);
+        return parallel::search_async(config, set, patterns, dir, files, max_open, out);
+    }
+    if let Some(jobs) = config.jobs.filter(|&jobs| jobs > 1) {
+        return parallel::search_threads(config, set, patterns, dir, files, jobs, out);
+    }
 
-    fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
+    for file in files {
+        search_file(config, set, patterns, dir, file, out);
+    }
+
+    Ok(())
+}
+
+// One file of a directory search. Errors are printed rather than returned:
+// one unreadable file shouldn't stop the search of the others.
+fn search_file(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    dir: &Path,
+    file: &str,
+    out: &mut impl Write
+) {
+    let path = dir.join(file).to_string_lossy().into_owned();
+
+    let searched = decompress::open(&path, config.search_zip)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|(reader, _)| search_named(config, set, patterns, &path, reader, out));
+    if let Err(e) = searched {
+        eprintln!("{path}: {e}");
+    }
+}
+
+// `search_stream` with the file's path in front of every match.
+fn search_named(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    path: &str,
+    reader: impl BufRead,
+    out: &mut impl Write
+) -> Result<(), Box<dyn Error>> {
+    let file_config = Config { file_path: Some(path.to_string()), with_filename: true, ..config.clone() };
+    search_stream(&file_config, set, patterns, reader, out)
+}
+
+// A matching line, from either the exact or the fuzzy search.
+enum Hit<'a> {
+    Patterns(PatternHit<'a>),
+    Fuzzy(FuzzyHit<'a>),
+}
+
+impl Hit<'_> {
+    fn line(&self) -> &str {
+        match self {
+            Hit::Patterns(hit) => hit.line,
+            Hit::Fuzzy(hit) => hit.line,
+        }
+    }
+}
+
+fn match_line<'a>(
+    config: &Config,
+    set: &PatternSet,
+    fuzzy_query: &[char],
+    line_number: usize,
+    line: &'a str
+) -> Option<Hit<'a>> {
+    match config.fuzzy {
+        Some(max_distance) =>
+            fuzzy_hit(fuzzy_query, max_distance, config.ignore_case, line_number, line).map(
+                Hit::Fuzzy
+            ),
+        None =>
+            pattern_hit(set, config.mode, config.ignore_case, line_number, line).map(
+                Hit::Patterns
+            ),
+    }
+}
+
+// Reads `reader` line by line and writes every match as soon as its line has
+// been read, instead of waiting for the end of the input.
+fn search_stream(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    mut reader: impl BufRead,
+    out: &mut impl Write
+) -> Result<(), Box<dyn Error>> {
+    let name = config.file_path.as_deref().unwrap_or("(standard input)");
+
+    // a BOM decides, unless --encoding was given
+    let encoding = match config.encoding {
+        Some(encoding) => encoding,
+        None => Encoding::detect(reader.fill_buf()?),
+    };
+
+    // anything that isn't UTF-8 is decoded first; -b then counts bytes of
+    // the decoded text
+    let invalid = if encoding == Encoding::Utf8 {
+        encoding::skip_utf8_bom(&mut reader)?;
+        search_lines_of(config, set, patterns, reader, out)?
+    } else {
+        let mut decoded = io::BufReader::new(encoding::Decoder::new(reader, encoding));
+        let invalid = search_lines_of(config, set, patterns, &mut decoded, out)?;
+        invalid || decoded.get_ref().had_invalid()
+    };
+
+    if invalid {
+        eprintln!("minigrep: {name}: invalid {encoding} was replaced with U+FFFD");
+    }
+
+    Ok(())
+}
+
+// The line by line part of `search_stream`, on UTF-8 input. Returns whether
+// some of the text wasn't valid UTF-8 (binary files don't count, nobody
+// expects them to be).
+fn search_lines_of(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    mut reader: impl BufRead,
+    out: &mut impl Write
+) -> Result<bool, Box<dyn Error>> {
+    let name = config.file_path.as_deref().unwrap_or("(standard input)");
+    // only --fuzzy uses it, and then there is exactly one pattern (with -f
+    // alone there may be no -e pattern at all)
+    let fuzzy_query: Vec<char> = match (config.fuzzy, patterns.first()) {
+        (Some(_), Some(query)) => query.chars().collect(),
+        _ => Vec::new(),
+    };
+
+    // peek at the first block without consuming it
+    let binary = binary::is_binary(reader.fill_buf()?);
+    if binary && config.binary_files == BinaryFiles::WithoutMatch {
+        return Ok(false);
+    }
+
+    let mut bytes = Vec::new();
+    let mut line_number = 0;
+    // where the next line starts in the input, for -b
+    let mut offset = 0;
+    let mut invalid = false;
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => {
-                return Err("Didn't get a query string");
+    loop {
+        bytes.clear();
+        let read = reader.read_until(b'\n', &mut bytes)?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        let line_offset = offset;
+        offset += read;
+
+        // same line endings as `str::lines`: "\n" or "\r\n"
+        if bytes.ends_with(b"\n") {
+            bytes.pop();
+            if bytes.ends_with(b"\r") {
+                bytes.pop();
             }
+        }
+
+        // invalid bytes become '\u{FFFD}' instead of failing the whole
+        // search; `from_utf8_lossy` only makes a copy when it had to replace
+        let line = String::from_utf8_lossy(&bytes);
+        if !binary && matches!(line, Cow::Owned(_)) {
+            invalid = true;
+        }
+
+        let Some(hit) = match_line(config, set, &fuzzy_query, line_number, &line) else {
+            continue;
         };
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => {
-                return Err("Didn't get a file path");
-            }
+        if binary && config.binary_files == BinaryFiles::Binary {
+            writeln!(out, "Binary file {name} matches")?;
+            return Ok(invalid);
+        }
+
+        write_hit(config, set, patterns, &hit, line_offset, out)?;
+    }
+
+    Ok(invalid)
+}
+
+fn write_hit(
+    config: &Config,
+    set: &PatternSet,
+    patterns: &[String],
+    hit: &Hit,
+    line_offset: usize,
+    out: &mut impl Write
+) -> io::Result<()> {
+    // when several files are searched, say which one the line is from
+    let path = config.file_path.as_deref().filter(|_| config.with_filename);
+
+    if config.json {
+        return match hit {
+            Hit::Fuzzy(hit) => writeln!(out, "{}", with_path(fuzzy_hit_to_json(hit), path)),
+            Hit::Patterns(hit) => writeln!(out, "{}", with_path(hit_to_json(hit, patterns), path)),
         };
+    }
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+    let line = hit.line();
+    let spans = hit_spans(config, set, hit);
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+    // -o: every match on a line of its own, and -b gives where the match is
+    // rather than where its line starts
+    if config.only_matching {
+        for &(start, end) in &spans {
+            write_prefix(config, path, column(line, start), line_offset + start, out)?;
+            writeln!(out, "{}", paint(config, &line[start..end], &[(0, end - start)]))?;
+        }
+        return Ok(());
+    }
+
+    write_prefix(config, path, column(line, spans[0].0), line_offset, out)?;
+
+    match hit {
+        Hit::Fuzzy(hit) =>
+            writeln!(
+                out,
+                "{} (distance {}, \"{}\" at {}..{})",
+                paint(config, hit.line, &spans),
+                hit.distance,
+                &hit.line[hit.start..hit.end],
+                hit.start,
+                hit.end
+            ),
+        Hit::Patterns(hit) =>
+            match &config.replace {
+                Some(replacement) => {
+                    let replaced = replace::replace_line(
+                        set,
+                        config.mode,
+                        config.ignore_case,
+                        hit.line,
+                        replacement
+                    );
+                    writeln!(out, "{replaced}")
+                }
+                None => writeln!(out, "{}", paint(config, hit.line, &spans)),
+            }
     }
 }
 
-// --snip--
+// With --color, wraps every span of `text` in the match color.
+fn paint<'a>(config: &Config, text: &'a str, spans: &[(usize, usize)]) -> Cow<'a, str> {
+    let Some(colors) = &config.color else {
+        return Cow::Borrowed(text);
+    };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    let mut painted = String::with_capacity(text.len());
+    let mut last = 0;
+    for &(start, end) in spans {
+        painted.push_str(&text[last..start]);
+        painted.push_str(&format!("\x1b[{}m{}\x1b[0m", colors.matched, &text[start..end]));
+        last = end;
+    }
+    painted.push_str(&text[last..]);
 
-    #[test]
-    fn one_result() {
-        let query = "duct";
-        let contents = "\
-Rust:
-safe, fast, productive.
-Pick three.";
+    Cow::Owned(painted)
+}
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+// The "path:column:offset:" in front of a match, each part only when asked for.
+fn write_prefix(
+    config: &Config,
+    path: Option<&str>,
+    column: usize,
+    byte_offset: usize,
+    out: &mut impl Write
+) -> io::Result<()> {
+    match (path, &config.color) {
+        (Some(path), Some(colors)) => write!(out, "\x1b[{}m{path}\x1b[0m:", colors.path)?,
+        (Some(path), None) => write!(out, "{path}:")?,
+        (None, _) => {}
+    }
+    if config.column {
+        write!(out, "{column}:")?;
+    }
+    if config.byte_offset {
+        write!(out, "{byte_offset}:")?;
+    }
+    Ok(())
+}
+
+// 1-based column of the char at byte `start`. Columns count chars (Unicode
+// scalar values), not bytes: in "Здравствуйте, мир" the "мир" starts at byte
+// 25 but is in column 15.
+fn column(line: &str, start: usize) -> usize {
+    line[..start].chars().count() + 1
+}
+
+// Byte spans of the matches in the line, left to right and not overlapping.
+fn hit_spans(config: &Config, set: &PatternSet, hit: &Hit) -> Vec<(usize, usize)> {
+    match hit {
+        Hit::Fuzzy(hit) => vec![(hit.start, hit.end)],
+        Hit::Patterns(hit) => {
+            let found = find_in_line(set, config.mode, hit.line, config.ignore_case);
+            leftmost_non_overlapping(found)
+                .into_iter()
+                .map(|found| (found.start, found.end))
+                .collect()
+        }
+    }
+}
+
+fn run_replace(config: &Config, set: &PatternSet) -> Result<(), Box<dyn Error>> {
+    if config.dry_run {
+        print!("{}", dry_run_diff(config, set)?);
+        return Ok(());
+    }
+
+    // `build` makes sure both are there for --in-place
+    let (Some(path), Some(replacement)) = (&config.file_path, &config.replace) else {
+        return Err("--in-place needs --replace and a file path".into());
+    };
+
+    let input = read_input(path, config.search_zip)?;
+    let contents = input.contents;
+
+    if input.compression != decompress::Compression::None {
+        return Err("--in-place can't rewrite a compressed file".into());
+    }
+    if input.binary {
+        return Err("--in-place won't rewrite a binary file".into());
+    }
+
+    let updated = replace::replace_contents(
+        set,
+        config.mode,
+        config.ignore_case,
+        &contents,
+        replacement
+    );
+
+    if updated != contents {
+        replace::write_atomically(path, &updated, config.backup.as_deref())?;
+    }
+
+    Ok(())
+}
+
+// --dry-run: the diff of what --in-place would do. Nothing gets written, so a
+// compressed file (with -z) can be shown too, decompressed.
+fn dry_run_diff(config: &Config, set: &PatternSet) -> Result<String, Box<dyn Error>> {
+    let (Some(path), Some(replacement)) = (&config.file_path, &config.replace) else {
+        return Err("--dry-run needs --replace and a file path".into());
+    };
+
+    // a compressed file can be shown, it just can't be written back
+    let input = read_input(path, config.search_zip)?;
+    if input.binary {
+        return Err("--dry-run won't diff a binary file".into());
+    }
+
+    let (old, new) = replace::replace_lines(set, config.mode, config.ignore_case, &input.contents, replacement);
+    Ok(replace::unified_diff(path, &old, &new))
+}
+
+fn hit_to_json(hit: &PatternHit, patterns: &[String]) -> String {
+    let matched: Vec<String> = hit.patterns
+        .iter()
+        .map(|&index| format!("\"{}\"", json_escape(&patterns[index])))
+        .collect();
+
+    format!(
+        "{{\"line_number\":{},\"line\":\"{}\",\"patterns\":[{}]}}",
+        hit.line_number,
+        json_escape(hit.line),
+        matched.join(",")
+    )
+}
+
+// Adds a "path" field in front of the other fields of a JSON object.
+fn with_path(json: String, path: Option<&str>) -> String {
+    match path {
+        Some(path) => format!("{{\"path\":\"{}\",{}", json_escape(path), &json[1..]),
+        None => json,
+    }
+}
+
+fn fuzzy_hit_to_json(hit: &FuzzyHit) -> String {
+    format!(
+        "{{\"line_number\":{},\"line\":\"{}\",\"distance\":{},\"start\":{},\"end\":{}}}",
+        hit.line_number,
+        json_escape(hit.line),
+        hit.distance,
+        hit.start,
+        hit.end
+    )
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// ANSI color codes (the "31" of "\x1b[31m") for the parts of the output
+// that --color highlights.
+#[derive(Debug, Clone, PartialEq)]
+struct Colors {
+    matched: &'static str,
+    path: &'static str,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors { matched: "31", path: "35" }
+    }
+}
+
+fn ansi_color(name: &str) -> Option<&'static str> {
+    match name {
+        "black" => Some("30"),
+        "red" => Some("31"),
+        "green" => Some("32"),
+        "yellow" => Some("33"),
+        "blue" => Some("34"),
+        "magenta" => Some("35"),
+        "cyan" => Some("36"),
+        "white" => Some("37"),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+struct Config {
+    // every -e PATTERN (or the single positional query)
+    patterns: Vec<String>,
+    // every -f FILE, read in `run` because reading can fail
+    pattern_files: Vec<String>,
+    // None (no path, or "-") reads standard input
+    file_path: Option<String>,
+    ignore_case: bool,
+    mode: MatchMode,
+    // --fuzzy K: maximum edit distance, None for exact matching
+    fuzzy: Option<usize>,
+    json: bool,
+    // --replace REPLACEMENT, and what to do with the replaced text
+    replace: Option<String>,
+    in_place: bool,
+    backup: Option<String>,
+    dry_run: bool,
+    // -z: look inside gzip / zstd files
+    search_zip: bool,
+    // --binary-files=TYPE / -a
+    binary_files: BinaryFiles,
+    // --follow: keep reading as the file grows
+    follow: bool,
+    // --index DIR: search the files of an indexed directory
+    index_dir: Option<String>,
+    // put the file name in front of every match (set when searching many files)
+    with_filename: bool,
+    // --encoding NAME: None means "UTF-8, unless there is a BOM"
+    encoding: Option<Encoding>,
+    // --color / --colors: None when the output isn't colored
+    color: Option<Colors>,
+    // --ignore GLOB: files the directory searches leave out
+    ignore: Vec<Glob>,
+    // the built-in file types and every --type-add, and what -t / -T chose
+    types: TypeTable,
+    type_filter: TypeFilter,
+    // --type-list: print the types instead of searching
+    type_list: bool,
+    // -r: `file_path` is a directory, search every file in it
+    recursive: bool,
+    // -j N: search that many files at once, on threads or (with --async) as
+    // futures
+    jobs: Option<usize>,
+    async_search: bool,
+    // --column, -b and -o
+    column: bool,
+    byte_offset: bool,
+    only_matching: bool,
+}
+
+// fn parse_config(args: &[String]) -> Config {
+//     let query = args[1].clone();
+//     let file_path = args[2].clone();
+
+//     Config { query, file_path }
+// }
+
+impl Config {
+    // fn new(args: &[String]) -> Config {
+    //     if args.len() < 3 {
+    //         panic!("not enough arguments");
+    //     }
+    //     let query = args[1].clone();
+    //     let file_path = args[2].clone();
+
+    //     Config { query, file_path }
+    // }
+
+    // returning a result instead of calling panic
+    // fn build(args: &[String]) -> Result<Config, &'static str> {
+    //     // error values will always be string literals that have the 'static lifetime.
+    //     if args.len() < 3 {
+    //         return Err("not enough arguments");
+    //     }
+
+    //     let query = args[1].clone();
+    //     let file_path = args[2].clone();
+    //     let ignore_case = env::var("IGNORE_CASE").is_ok();
+
+    //     Ok(Config { query, file_path, ignore_case })
+    // }
+
+    fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
+        args.next();
+
+        let mut options = Options::new();
+        while let Some(arg) = args.next() {
+            options.apply(arg, &mut args)?;
+        }
+
+        let Options {
+            mut patterns,
+            pattern_files,
+            json,
+            word,
+            whole_line,
+            fuzzy,
+            replace,
+            in_place,
+            backup,
+            dry_run,
+            search_zip,
+            binary_files,
+            follow,
+            index_dir,
+            encoding,
+            color_when,
+            colors,
+            ignore,
+            types,
+            selected_types,
+            rejected_types,
+            type_list,
+            recursive,
+            jobs,
+            async_search,
+            column,
+            byte_offset,
+            only_matching,
+            positional,
+        } = options;
+
+        let mut positional = positional.into_iter();
+
+        // without -e or -f the first positional argument is the query
+        // (--type-list doesn't search, so it doesn't need one)
+        if patterns.is_empty() && pattern_files.is_empty() && !type_list {
+            match positional.next() {
+                Some(arg) => patterns.push(arg),
+                None => {
+                    return Err("Didn't get a query string");
+                }
+            }
+        }
+
+        // let file_path = match positional.next() {
+        //     Some(arg) => arg,
+        //     None => {
+        //         return Err("Didn't get a file path");
+        //     }
+        // };
+
+        // like most Unix tools, no path or "-" means standard input
+        let file_path = positional.next().filter(|path| path != "-");
+
+        if positional.next().is_some() {
+            return Err("Too many arguments, expected a query and one path");
+        }
+
+        if fuzzy.is_some() && (patterns.len() != 1 || !pattern_files.is_empty()) {
+            return Err("--fuzzy works with a single query");
+        }
+        if fuzzy.is_some() && (word || whole_line) {
+            return Err("--fuzzy can't be combined with -w or -x");
+        }
+
+        if replace.is_none() && (in_place || dry_run || backup.is_some()) {
+            return Err("--in-place, --dry-run and --backup need --replace");
+        }
+        if (in_place || dry_run) && file_path.is_none() {
+            return Err("--in-place and --dry-run need a file path");
+        }
+        if follow && file_path.is_none() {
+            return Err("--follow needs a file path");
+        }
+        if follow && (in_place || dry_run) {
+            return Err("--follow can't be combined with --in-place or --dry-run");
+        }
+        if recursive && file_path.is_none() {
+            return Err("-r needs a directory");
+        }
+        if recursive && (follow || in_place || dry_run || index_dir.is_some()) {
+            return Err("-r can't be combined with --follow, --in-place, --dry-run or --index");
+        }
+        if (jobs.is_some() || async_search) && !recursive && index_dir.is_none() {
+            return Err("-j and --async only apply to -r and --index");
+        }
+        if index_dir.is_some() && file_path.is_some() {
+            return Err("--index searches its directory, don't give a file path too");
+        }
+        if index_dir.is_some() && (follow || in_place || dry_run) {
+            return Err("--index can't be combined with --follow, --in-place or --dry-run");
+        }
+        if json && (column || byte_offset || only_matching) {
+            return Err("--column, -b and -o can't be combined with --json");
+        }
+        if encoding.is_some() && (in_place || dry_run) {
+            return Err("--in-place and --dry-run only rewrite UTF-8 files");
+        }
+        if only_matching && replace.is_some() {
+            return Err("-o can't be combined with --replace");
+        }
+        if backup.is_some() && !in_place {
+            return Err("--backup only makes sense with --in-place");
+        }
+        if replace.is_some() && (fuzzy.is_some() || json) {
+            return Err("--replace can't be combined with --fuzzy or --json");
+        }
+        if let Some(replacement) = &replace {
+            replace::check_replacement(replacement)?;
+        }
+
+        // after the loop, so `-t web --type-add 'web:*.html'` works too
+        let Some(type_filter) = TypeFilter::new(&types, &selected_types, &rejected_types) else {
+            return Err("Unknown file type, see --type-list");
+        };
+
+        let ignore_case = env::var("IGNORE_CASE").is_ok();
+
+        // "auto" colors only a terminal, never a pipe or a file; JSON is
+        // never colored
+        let color = match color_when.as_str() {
+            _ if json => None,
+            "always" => Some(colors),
+            "auto" if io::stdout().is_terminal() => Some(colors),
+            _ => None,
+        };
+
+        // like grep, -x wins when both are given
+        let mode = if whole_line {
+            MatchMode::Line
+        } else if word {
+            MatchMode::Word
+        } else {
+            MatchMode::Substring
+        };
+
+        Ok(Config {
+            patterns,
+            pattern_files,
+            file_path,
+            ignore_case,
+            mode,
+            fuzzy,
+            json,
+            replace,
+            in_place,
+            backup,
+            dry_run,
+            search_zip,
+            binary_files,
+            follow,
+            index_dir,
+            with_filename: false,
+            encoding,
+            color,
+            ignore,
+            types,
+            type_filter,
+            type_list,
+            recursive,
+            jobs,
+            async_search,
+            column,
+            byte_offset,
+            only_matching,
+        })
+    }
+}
+
+// What the options asked for, one at a time. `Config::build` checks that
+// they go together; the config file uses `apply` on its own to check each
+// line as it reads it.
+struct Options {
+    patterns: Vec<String>,
+    pattern_files: Vec<String>,
+    json: bool,
+    word: bool,
+    whole_line: bool,
+    fuzzy: Option<usize>,
+    replace: Option<String>,
+    in_place: bool,
+    backup: Option<String>,
+    dry_run: bool,
+    search_zip: bool,
+    binary_files: BinaryFiles,
+    follow: bool,
+    index_dir: Option<String>,
+    encoding: Option<Encoding>,
+    color_when: String,
+    colors: Colors,
+    ignore: Vec<Glob>,
+    types: TypeTable,
+    selected_types: Vec<String>,
+    rejected_types: Vec<String>,
+    type_list: bool,
+    recursive: bool,
+    jobs: Option<usize>,
+    async_search: bool,
+    column: bool,
+    byte_offset: bool,
+    only_matching: bool,
+    positional: Vec<String>,
+}
+
+impl Options {
+    fn new() -> Options {
+        Options {
+            patterns: Vec::new(),
+            pattern_files: Vec::new(),
+            json: false,
+            word: false,
+            whole_line: false,
+            fuzzy: None,
+            replace: None,
+            in_place: false,
+            backup: None,
+            dry_run: false,
+            search_zip: false,
+            binary_files: BinaryFiles::Binary,
+            follow: false,
+            index_dir: None,
+            encoding: None,
+            color_when: String::from("auto"),
+            colors: Colors::default(),
+            ignore: Vec::new(),
+            types: TypeTable::with_built_ins(),
+            selected_types: Vec::new(),
+            rejected_types: Vec::new(),
+            type_list: false,
+            recursive: false,
+            jobs: None,
+            async_search: false,
+            column: false,
+            byte_offset: false,
+            only_matching: false,
+            positional: Vec::new(),
+        }
+    }
+
+    // `arg`, and its value taken from `args` if it has one
+    fn apply(&mut self, arg: String, args: &mut impl Iterator<Item = String>) -> Result<(), &'static str> {
+        match arg.as_str() {
+            "-e" =>
+                match args.next() {
+                    Some(pattern) => self.patterns.push(pattern),
+                    None => {
+                        return Err("Didn't get a pattern after -e");
+                    }
+                }
+            "-f" =>
+                match args.next() {
+                    Some(file) => self.pattern_files.push(file),
+                    None => {
+                        return Err("Didn't get a pattern file after -f");
+                    }
+                }
+            "--json" => {
+                self.json = true;
+            }
+            // every switch has a --no- form that turns it back off, so the
+            // command line can undo what the config file turned on
+            "--no-json" => {
+                self.json = false;
+            }
+            "-w" | "--word-regexp" => {
+                self.word = true;
+            }
+            "--no-word-regexp" => {
+                self.word = false;
+            }
+            "-x" | "--line-regexp" => {
+                self.whole_line = true;
+            }
+            "--no-line-regexp" => {
+                self.whole_line = false;
+            }
+            "--fuzzy" =>
+                match args.next().and_then(|k| k.parse().ok()) {
+                    Some(k) => {
+                        self.fuzzy = Some(k);
+                    }
+                    None => {
+                        return Err("--fuzzy needs a maximum edit distance");
+                    }
+                }
+            "--replace" =>
+                match args.next() {
+                    Some(replacement) => {
+                        self.replace = Some(replacement);
+                    }
+                    None => {
+                        return Err("Didn't get a replacement after --replace");
+                    }
+                }
+            "--backup" =>
+                match args.next() {
+                    Some(suffix) => {
+                        self.backup = Some(suffix);
+                    }
+                    None => {
+                        return Err("Didn't get a suffix after --backup");
+                    }
+                }
+            "--in-place" => {
+                self.in_place = true;
+            }
+            "--no-in-place" => {
+                self.in_place = false;
+            }
+            "--dry-run" => {
+                self.dry_run = true;
+            }
+            "--no-dry-run" => {
+                self.dry_run = false;
+            }
+            "-z" | "--search-zip" => {
+                self.search_zip = true;
+            }
+            "--no-search-zip" => {
+                self.search_zip = false;
+            }
+            "-a" | "--text" => {
+                self.binary_files = BinaryFiles::Text;
+            }
+            "--no-text" => {
+                if self.binary_files == BinaryFiles::Text {
+                    self.binary_files = BinaryFiles::Binary;
+                }
+            }
+            "--follow" => {
+                self.follow = true;
+            }
+            "--no-follow" => {
+                self.follow = false;
+            }
+            "--encoding" =>
+                match args.next().as_deref().map(Encoding::parse) {
+                    Some(Some(name)) => {
+                        self.encoding = Some(name);
+                    }
+                    Some(None) => {
+                        return Err("--encoding must be utf-8, utf-16le, utf-16be or latin1");
+                    }
+                    None => {
+                        return Err("Didn't get an encoding after --encoding");
+                    }
+                }
+            "--color" =>
+                match args.next() {
+                    Some(when) if ["auto", "always", "never"].contains(&when.as_str()) => {
+                        self.color_when = when;
+                    }
+                    _ => {
+                        return Err("--color must be auto, always or never");
+                    }
+                }
+            "--colors" => {
+                let spec = args.next().unwrap_or_default();
+                match spec.split_once(':').map(|(what, name)| (what, ansi_color(name))) {
+                    Some(("match", Some(code))) => {
+                        self.colors.matched = code;
+                    }
+                    Some(("path", Some(code))) => {
+                        self.colors.path = code;
+                    }
+                    _ => {
+                        return Err("--colors must look like match:red or path:magenta");
+                    }
+                }
+            }
+            "--ignore" =>
+                match args.next().map(|glob| Glob::new(&glob)) {
+                    Some(Ok(glob)) => self.ignore.push(glob),
+                    _ => {
+                        return Err("--ignore needs a glob, like target or *.min.js");
+                    }
+                }
+            "--type-add" =>
+                match args.next().as_deref().and_then(parse_type_def) {
+                    Some((name, globs)) => self.types.add(&name, globs),
+                    None => {
+                        return Err("--type-add needs NAME:GLOB, like web:*.{html,css}");
+                    }
+                }
+            "-t" | "--type" =>
+                match args.next() {
+                    Some(name) => self.selected_types.push(name),
+                    None => {
+                        return Err("Didn't get a file type after -t");
+                    }
+                }
+            "-T" | "--type-not" =>
+                match args.next() {
+                    Some(name) => self.rejected_types.push(name),
+                    None => {
+                        return Err("Didn't get a file type after -T");
+                    }
+                }
+            "--type-list" => {
+                self.type_list = true;
+            }
+            "--no-type-list" => {
+                self.type_list = false;
+            }
+            "-r" | "--recursive" => {
+                self.recursive = true;
+            }
+            "--no-recursive" => {
+                self.recursive = false;
+            }
+            "-j" | "--jobs" =>
+                match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => {
+                        self.jobs = Some(n);
+                    }
+                    _ => {
+                        return Err("-j needs a number of files to search at once");
+                    }
+                }
+            "--async" => {
+                self.async_search = true;
+            }
+            "--no-async" => {
+                self.async_search = false;
+            }
+            // handled in `with_config_file`, before we get here
+            "--no-config" => {}
+            "--column" => {
+                self.column = true;
+            }
+            "--no-column" => {
+                self.column = false;
+            }
+            "-b" | "--byte-offset" => {
+                self.byte_offset = true;
+            }
+            "--no-byte-offset" => {
+                self.byte_offset = false;
+            }
+            "-o" | "--only-matching" => {
+                self.only_matching = true;
+            }
+            "--no-only-matching" => {
+                self.only_matching = false;
+            }
+            "--index" =>
+                match args.next() {
+                    Some(dir) => {
+                        self.index_dir = Some(dir);
+                    }
+                    None => {
+                        return Err("Didn't get a directory after --index");
+                    }
+                }
+            _ if arg.starts_with("--binary-files=") =>
+                match BinaryFiles::parse(&arg["--binary-files=".len()..]) {
+                    Some(policy) => {
+                        self.binary_files = policy;
+                    }
+                    None => {
+                        return Err("--binary-files must be binary, text or without-match");
+                    }
+                }
+            // everything after -- is a query or a path, even if it
+            // starts with -
+            "--" => self.positional.extend(args.by_ref()),
+            // a typo like --colum would otherwise become the query
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err("Unknown option (a query that starts with - goes after -e or --)");
+            }
+            _ => self.positional.push(arg),
+        }
+
+        Ok(())
+    }
+}
+
+// "web:*.html,*.css" (or "web:*.{html,css}") into a named list of globs.
+fn parse_type_def(spec: &str) -> Option<(String, Vec<Glob>)> {
+    let (name, globs) = spec.split_once(':')?;
+    if name.is_empty() || globs.is_empty() {
+        return None;
+    }
+
+    let globs = glob
+        ::split_list(globs)
+        .into_iter()
+        .map(|glob| Glob::new(glob).ok())
+        .collect::<Option<Vec<Glob>>>()?;
+    Some((name.to_string(), globs))
+}
+
+// --snip--
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ sync::mpsc, thread, time::Duration };
+
+    #[test]
+    fn one_result() {
+        let query = "duct";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+    }
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        let mut all = vec![String::from("minigrep")];
+        all.extend(list.iter().map(|a| a.to_string()));
+        all.into_iter()
+    }
+
+    #[test]
+    fn build_collects_repeated_patterns() {
+        let config = Config::build(
+            args(&["-e", "fast", "-f", "banned.txt", "-e", "three", "poem.txt"])
+        ).unwrap();
+
+        assert_eq!(vec!["fast", "three"], config.patterns);
+        assert_eq!(vec!["banned.txt"], config.pattern_files);
+        assert_eq!(Some(String::from("poem.txt")), config.file_path);
+    }
+
+    #[test]
+    fn build_still_takes_a_positional_query() {
+        let config = Config::build(args(&["duct", "poem.txt", "--json"])).unwrap();
+
+        assert_eq!(vec!["duct"], config.patterns);
+        assert_eq!(Some(String::from("poem.txt")), config.file_path);
+        assert!(config.json);
+    }
+
+    #[test]
+    fn build_rejects_unknown_options() {
+        assert!(Config::build(args(&["--colum", "duct", "poem.txt"])).is_err());
+        assert!(Config::build(args(&["duct", "poem.txt", "-q"])).is_err());
+
+        let config = Config::build(args(&["--", "--colum", "-"])).unwrap();
+        assert_eq!(vec!["--colum"], config.patterns);
+        assert_eq!(None, config.file_path);
+    }
+
+    #[test]
+    fn index_is_still_a_query() {
+        let search: Vec<String> = args(&["index", "notes.txt"]).collect();
+        assert!(!is_index_command(&search));
+
+        let config = Config::build(search.into_iter()).unwrap();
+        assert_eq!(vec!["index"], config.patterns);
+        assert_eq!(Some(String::from("notes.txt")), config.file_path);
+
+        assert!(is_index_command(&args(&["index", "build", "docs"]).collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn build_rejects_extra_arguments() {
+        assert!(Config::build(args(&["duct", "poem.txt", "notes.txt"])).is_err());
+        assert!(Config::build(args(&["-e", "duct", "poem.txt", "notes.txt"])).is_err());
+    }
+
+    #[test]
+    fn any_pattern_matches() {
+        let patterns = PatternSet::new(vec![String::from("duct"), String::from("Pick")]);
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        let hits = search_patterns(&patterns, MatchMode::Substring, contents);
+
+        assert_eq!(
+            vec![
+                PatternHit { line_number: 2, line: "safe, fast, productive.", patterns: vec![0] },
+                PatternHit { line_number: 3, line: "Pick three.", patterns: vec![1] }
+            ],
+            hits
+        );
+    }
+
+    #[test]
+    fn reports_every_pattern_in_a_line() {
+        let patterns = PatternSet::new(vec![String::from("rust"), String::from("me")]);
+        let contents = "\
+Rust:
+Trust me.";
+
+        let hits = search_patterns_case_insensitive(&patterns, MatchMode::Substring, contents);
+
+        assert_eq!(vec![0], hits[0].patterns);
+        assert_eq!(vec![0, 1], hits[1].patterns);
+    }
+
+    fn lines<'a>(hits: Vec<PatternHit<'a>>) -> Vec<&'a str> {
+        hits.into_iter()
+            .map(|hit| hit.line)
+            .collect()
+    }
+
+    #[test]
+    fn word_mode_needs_boundaries() {
+        let patterns = PatternSet::new(vec![String::from("rust")]);
+        let contents = "\
+rust:
+trust me.
+rusty nail
+_rust
+use rust_std;
+a rust-belt town";
+
+        assert_eq!(
+            vec!["rust:", "a rust-belt town"],
+            lines(search_patterns(&patterns, MatchMode::Word, contents))
+        );
+    }
+
+    #[test]
+    fn word_mode_tries_every_occurrence() {
+        let patterns = PatternSet::new(vec![String::from("art")]);
+
+        assert_eq!(
+            vec!["cart art"],
+            lines(search_patterns(&patterns, MatchMode::Word, "cart art\ncartart"))
+        );
+    }
+
+    #[test]
+    fn word_boundaries_are_unicode_aware() {
+        let patterns = PatternSet::new(vec![String::from("caf")]);
+        let contents = "\
+café
+caf é
+здравствуй caf";
+
+        assert_eq!(
+            vec!["caf é", "здравствуй caf"],
+            lines(search_patterns(&patterns, MatchMode::Word, contents))
+        );
+
+        let patterns = PatternSet::new(vec![String::from("зд")]);
+        assert!(search_patterns(&patterns, MatchMode::Word, "здравствуйте").is_empty());
+    }
+
+    #[test]
+    fn line_mode_needs_the_whole_line() {
+        let patterns = PatternSet::new(vec![String::from("Pick three.")]);
+        let contents = "\
+Pick three.
+Pick three. Or four.";
+
+        assert_eq!(
+            vec!["Pick three."],
+            lines(search_patterns(&patterns, MatchMode::Line, contents))
+        );
+    }
+
+    #[test]
+    fn modes_work_case_insensitively() {
+        let patterns = PatternSet::new(vec![String::from("rust")]);
+        let contents = "\
+Rust
+Trust me.
+RUST is fun";
+
+        assert_eq!(
+            vec!["Rust", "RUST is fun"],
+            lines(search_patterns_case_insensitive(&patterns, MatchMode::Word, contents))
+        );
+        assert_eq!(
+            vec!["Rust"],
+            lines(search_patterns_case_insensitive(&patterns, MatchMode::Line, contents))
+        );
+    }
+
+    #[test]
+    fn build_reads_match_mode_flags() {
+        let config = Config::build(args(&["-w", "rust", "poem.txt"])).unwrap();
+        assert_eq!(MatchMode::Word, config.mode);
+
+        let config = Config::build(args(&["--word-regexp", "-x", "rust", "poem.txt"])).unwrap();
+        assert_eq!(MatchMode::Line, config.mode);
+    }
+
+    fn spans<'a>(hits: &[FuzzyHit<'a>]) -> Vec<(usize, &'a str)> {
+        hits.iter()
+            .map(|hit| (hit.distance, &hit.line[hit.start..hit.end]))
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_exact_match_has_distance_zero() {
+        let hits = search_fuzzy("duct", 1, "safe, fast, productive.");
+
+        assert_eq!(vec![(0, "duct")], spans(&hits));
+        assert_eq!((15, 19), (hits[0].start, hits[0].end));
+    }
+
+    #[test]
+    fn fuzzy_finds_insertions() {
+        let hits = search_fuzzy("hello", 1, "say hel-lo world");
+
+        assert_eq!(vec![(1, "hel-lo")], spans(&hits));
+    }
+
+    #[test]
+    fn fuzzy_finds_deletions() {
+        let hits = search_fuzzy("hello", 1, "say helo there");
+
+        assert_eq!(vec![(1, "helo")], spans(&hits));
+    }
+
+    #[test]
+    fn fuzzy_finds_substitutions() {
+        let hits = search_fuzzy("hello", 1, "jello world");
+
+        assert_eq!(vec![(1, "jello")], spans(&hits));
+    }
+
+    #[test]
+    fn fuzzy_respects_the_bound() {
+        let contents = "\
+conection refused
+connectiom refsued
+disconnected";
+
+        let hits = search_fuzzy("connection", 1, contents);
+        assert_eq!(vec![(1, "conection"), (1, "connectiom")], spans(&hits));
+
+        let hits = search_fuzzy("connection", 0, contents);
+        assert!(hits.is_empty());
+
+        let hits = search_fuzzy("refused", 2, contents);
+        // a swapped pair of letters costs two substitutions
+        assert_eq!(vec![(0, "refused"), (2, "refsued")], spans(&hits));
+    }
+
+    #[test]
+    fn fuzzy_case_insensitive_keeps_original_span() {
+        let hits = search_fuzzy_case_insensitive("здравствуйте", 1, "Привет, ЗДРАВСТВУТЕ!");
+
+        assert_eq!(vec![(1, "ЗДРАВСТВУТЕ")], spans(&hits));
+    }
+
+    #[test]
+    fn build_rejects_fuzzy_with_several_patterns() {
+        assert!(Config::build(args(&["--fuzzy", "1", "-e", "a", "-e", "b", "f.txt"])).is_err());
+        assert!(Config::build(args(&["--fuzzy", "x", "a", "f.txt"])).is_err());
+        assert_eq!(Some(2), Config::build(args(&["--fuzzy", "2", "a", "f.txt"])).unwrap().fuzzy);
+    }
+
+    #[test]
+    fn build_reads_replace_flags() {
+        let config = Config::build(
+            args(&["--replace", "Ferris", "--in-place", "--backup", ".bak", "rust", "poem.txt"])
+        ).unwrap();
+
+        assert_eq!(Some(String::from("Ferris")), config.replace);
+        assert!(config.in_place);
+        assert_eq!(Some(String::from(".bak")), config.backup);
+        assert_eq!(vec!["rust"], config.patterns);
+
+        assert!(Config::build(args(&["--replace", "<$0>", "rust", "poem.txt"])).is_ok());
+        assert!(Config::build(args(&["--replace", "$1", "rust", "poem.txt"])).is_err());
+    }
+
+    #[test]
+    fn build_reads_search_zip() {
+        assert!(Config::build(args(&["-z", "error", "app.log.gz"])).unwrap().search_zip);
+        assert!(Config::build(args(&["--search-zip", "error", "app.log.zst"])).unwrap().search_zip);
+        assert!(!Config::build(args(&["error", "app.log"])).unwrap().search_zip);
+    }
+
+    #[test]
+    fn build_reads_binary_policy() {
+        let config = Config::build(args(&["--binary-files=without-match", "a", "f"])).unwrap();
+        assert_eq!(BinaryFiles::WithoutMatch, config.binary_files);
+
+        // -a is the same as --binary-files=text, the last flag wins
+        let config = Config::build(args(&["--binary-files=without-match", "-a", "a", "f"])).unwrap();
+        assert_eq!(BinaryFiles::Text, config.binary_files);
+
+        assert_eq!(BinaryFiles::Binary, Config::build(args(&["a", "f"])).unwrap().binary_files);
+        assert!(Config::build(args(&["--binary-files=maybe", "a", "f"])).is_err());
+    }
+
+    fn binary_fixture(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("minigrep-binary-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // a made up executable: header, NULs, invalid UTF-8 and a string table
+        let mut bytes = vec![0x7f, b'E', b'L', b'F', 0, 0, 0xff, 0xfe];
+        bytes.extend(b"\nusage: tool [--verbose]\nerror: file not found\n");
+        bytes.extend([0u8; 16]);
+
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn binary_files_are_read_lossily() {
+        let path = binary_fixture("tool.bin");
+
+        let input = read_input(&path, false).unwrap();
+
+        assert!(input.binary);
+        assert!(input.contents.contains("error: file not found"));
+        assert!(input.contents.contains('\u{FFFD}'));
+    }
+
+    #[test]
+    fn dry_run_decompresses_but_in_place_refuses() {
+        use flate2::{ Compression as Level, write::GzEncoder };
+
+        let dir = env::temp_dir().join(format!("minigrep-dry-run-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(b"safe, fast, productive.\n").unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = Config::build(args(&["-z", "--replace", "SPEEDY", "--dry-run", "fast", path])).unwrap();
+        let set = PatternSet::new(config.patterns.clone());
+        assert_eq!(
+            format!("--- a/{path}\n+++ b/{path}\n@@ -1,1 +1,1 @@\n-safe, fast, productive.\n+safe, SPEEDY, productive.\n"),
+            dry_run_diff(&config, &set).unwrap()
+        );
+
+        let config = Config::build(args(&["-z", "--replace", "SPEEDY", "--in-place", "fast", path])).unwrap();
+        let error = run_replace(&config, &set).unwrap_err();
+        assert_eq!("--in-place can't rewrite a compressed file", error.to_string());
+    }
+
+    #[test]
+    fn dry_run_refuses_binary_files_like_in_place() {
+        let path = binary_fixture("dry-run.bin");
+        let set = PatternSet::new(vec![String::from("usage")]);
+
+        for mode in ["--dry-run", "--in-place"] {
+            let config = Config::build(args(&["--replace", "help", mode, "usage", &path])).unwrap();
+            let error = match mode {
+                "--dry-run" => dry_run_diff(&config, &set).unwrap_err(),
+                _ => run_replace(&config, &set).unwrap_err(),
+            };
+            assert!(error.to_string().contains("binary file"), "{error}");
+        }
+    }
+
+    fn stream(config: &Config, input: &[u8]) -> String {
+        let set = PatternSet::new(config.patterns.clone());
+        let mut out = Vec::new();
+        search_stream(config, &set, &config.patterns, input, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn binary_match_prints_a_summary() {
+        let path = binary_fixture("match.bin");
+        let bytes = fs::read(&path).unwrap();
+
+        let config = Config::build(args(&["error", &path])).unwrap();
+        assert_eq!(format!("Binary file {path} matches\n"), stream(&config, &bytes));
+
+        let config = Config::build(args(&["warning", &path])).unwrap();
+        assert_eq!("", stream(&config, &bytes));
+    }
+
+    #[test]
+    fn binary_policy_text_and_without_match() {
+        let path = binary_fixture("policy.bin");
+        let bytes = fs::read(&path).unwrap();
+
+        let config = Config::build(args(&["-a", "error", &path])).unwrap();
+        assert_eq!("error: file not found\n", stream(&config, &bytes));
+
+        let config = Config::build(args(&["--binary-files=without-match", "error", &path])).unwrap();
+        assert_eq!("", stream(&config, &bytes));
+    }
+
+    #[test]
+    fn text_files_are_not_binary() {
+        let dir = env::temp_dir().join(format!("minigrep-binary-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt");
+        fs::write(&path, "I'm nobody! Who are you?\n").unwrap();
+
+        assert!(!read_input(path.to_str().unwrap(), false).unwrap().binary);
+    }
+
+    #[test]
+    fn build_falls_back_to_stdin() {
+        assert_eq!(None, Config::build(args(&["error"])).unwrap().file_path);
+        assert_eq!(None, Config::build(args(&["error", "-"])).unwrap().file_path);
+        assert!(Config::build(args(&["--replace", "x", "--in-place", "error"])).is_err());
+    }
+
+    #[test]
+    fn stream_prints_matching_lines() {
+        let config = Config::build(args(&["-e", "fast", "-e", "three"])).unwrap();
+
+        assert_eq!(
+            "safe, fast, productive.\nPick three.\n",
+            stream(&config, b"Rust:\r\nsafe, fast, productive.\r\nPick three.")
+        );
+    }
+
+    #[test]
+    fn columns_count_chars_not_bytes() {
+        let config = Config::build(args(&["--column", "мир"])).unwrap();
+
+        // "Здравствуйте, " is 14 chars but 25 bytes
+        assert_eq!("15:Здравствуйте, мир\n", stream(&config, "Здравствуйте, мир\n".as_bytes()));
+        assert_eq!(3, column("été", 3));
+    }
+
+    #[test]
+    fn byte_offsets_point_at_line_starts() {
+        let config = Config::build(args(&["-b", "three"])).unwrap();
+
+        // "Rust:\r\n" is 7 bytes, "safe, fast, productive.\n" 24 more
+        assert_eq!(
+            "31:Pick three.\n",
+            stream(&config, b"Rust:\r\nsafe, fast, productive.\nPick three.\n")
+        );
+    }
+
+    #[test]
+    fn only_matching_prints_every_match() {
+        let config = Config::build(args(&["-o", "-e", "fast", "-e", "safe"])).unwrap();
+        assert_eq!("safe\nfast\n", stream(&config, b"Rust:\nsafe, fast, productive.\n"));
+
+        // with -b, the offset of the match itself
+        let config = Config::build(args(&["-o", "-b", "--column", "в"])).unwrap();
+        assert_eq!(
+            "5:14:в\n8:20:в\n",
+            stream(&config, "Rust:\nЗдравствуйте\n".as_bytes())
+        );
+    }
+
+    #[test]
+    fn build_rejects_positions_with_json() {
+        assert!(Config::build(args(&["--json", "-o", "a", "f"])).is_err());
+        assert!(Config::build(args(&["-o", "--replace", "b", "a", "f"])).is_err());
+        assert!(Config::build(args(&["--column", "-b", "a", "f"])).unwrap().column);
+    }
+
+    #[test]
+    fn stream_replaces_invalid_utf8_in_text() {
+        let config = Config::build(args(&["a"])).unwrap();
+        let set = PatternSet::new(config.patterns.clone());
+        let mut out = Vec::new();
+
+        let invalid = search_lines_of(&config, &set, &config.patterns, &b"a\xff\nb\n"[..], &mut out).unwrap();
+
+        assert!(invalid);
+        assert_eq!("a\u{FFFD}\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn stream_with_only_pattern_files() {
+        // `minigrep -f pats.txt`: no -e pattern, run() has read "b" from the file
+        let config = Config::build(args(&["-f", "pats.txt"])).unwrap();
+        assert!(config.patterns.is_empty());
+        let patterns = vec![String::from("b")];
+        let set = PatternSet::new(patterns.clone());
+        let mut out = Vec::new();
+
+        search_lines_of(&config, &set, &patterns, &b"a\nb\n"[..], &mut out).unwrap();
+
+        assert_eq!("b\n", String::from_utf8(out).unwrap());
+    }
+
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for unit in std::iter::once(0xfeff).chain(text.encode_utf16()) {
+            if little_endian {
+                bytes.extend(unit.to_le_bytes());
+            } else {
+                bytes.extend(unit.to_be_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn stream_decodes_utf16_by_its_bom() {
+        let text = "Rust:\r\nЗдравствуйте\r\nPick three.\r\n";
+
+        let config = Config::build(args(&["--column", "вуй"])).unwrap();
+        assert_eq!("8:Здравствуйте\n", stream(&config, &utf16(text, true)));
+        assert_eq!("8:Здравствуйте\n", stream(&config, &utf16(text, false)));
+    }
+
+    #[test]
+    fn stream_decodes_the_encoding_it_is_told() {
+        let config = Config::build(args(&["--encoding", "latin1", "café"])).unwrap();
+        assert_eq!("un café\n", stream(&config, b"un caf\xe9\n"));
+
+        // without --encoding the same bytes aren't UTF-8
+        let config = Config::build(args(&["caf"])).unwrap();
+        assert_eq!("un caf\u{FFFD}\n", stream(&config, b"un caf\xe9\n"));
+    }
+
+    #[test]
+    fn stream_skips_a_utf8_bom() {
+        let config = Config::build(args(&["-x", "Rust:"])).unwrap();
+
+        assert_eq!("Rust:\n", stream(&config, b"\xef\xbb\xbfRust:\n"));
+    }
+
+    #[test]
+    fn build_reads_encoding() {
+        let config = Config::build(args(&["--encoding", "UTF-16LE", "a", "f"])).unwrap();
+        assert_eq!(Some(Encoding::Utf16Le), config.encoding);
+
+        assert!(Config::build(args(&["--encoding", "klingon", "a", "f"])).is_err());
+        assert_eq!(Some("Didn't get an encoding after --encoding"), Config::build(args(&["--encoding"])).err());
+    }
+
+    // A pipe that delivers one chunk at a time, like `tail -f` would.
+    struct Pipe(mpsc::Receiver<Vec<u8>>, Vec<u8>);
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.1.is_empty() {
+                match self.0.recv() {
+                    Ok(chunk) => {
+                        self.1 = chunk;
+                    }
+                    Err(_) => {
+                        return Ok(0);
+                    }
+                }
+            }
+            let n = buf.len().min(self.1.len());
+            buf[..n].copy_from_slice(&self.1[..n]);
+            self.1.drain(..n);
+            Ok(n)
+        }
+    }
+
+    // Output that is sent back line by line as it is written.
+    struct Lines(mpsc::Sender<String>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.send(String::from_utf8_lossy(buf).into_owned()).unwrap();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_prints_matches_before_the_input_ends() {
+        let (input, pipe) = mpsc::channel();
+        let (output, printed) = mpsc::channel();
+
+        let searcher = thread::spawn(move || {
+            let config = Config::build(args(&["ERROR"])).unwrap();
+            let set = PatternSet::new(config.patterns.clone());
+            let reader = io::BufReader::new(Pipe(pipe, Vec::new()));
+            let mut out = io::LineWriter::new(Lines(output));
+            search_stream(&config, &set, &config.patterns, reader, &mut out).unwrap();
+        });
+
+        input.send(b"INFO starting\nERROR disk full\n".to_vec()).unwrap();
+        // the pipe is still open, yet the match already came out
+        let first = printed.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!("ERROR disk full\n", first);
+
+        input.send(b"ERROR again\n".to_vec()).unwrap();
+        assert_eq!("ERROR again\n", printed.recv_timeout(Duration::from_secs(5)).unwrap());
+
+        drop(input);
+        searcher.join().unwrap();
+    }
+
+    #[test]
+    fn follow_prints_matches_appended_later() {
+        let dir = env::temp_dir().join(format!("minigrep-follow-run-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        fs::write(&log, "INFO boot\nERROR early\n").unwrap();
+        let path = log.to_str().unwrap().to_string();
+
+        let follower = follow::Follower::open(&path).unwrap();
+        let stop = follower.stop_handle();
+        let (output, printed) = mpsc::channel();
+
+        let searcher = thread::spawn(move || {
+            let config = Config::build(args(&["--follow", "ERROR", &path])).unwrap();
+            let set = PatternSet::new(config.patterns.clone());
+            let mut out = io::LineWriter::new(Lines(output));
+            let reader = io::BufReader::new(follower);
+            search_stream(&config, &set, &config.patterns, reader, &mut out).unwrap();
+        });
+
+        assert_eq!("ERROR early\n", printed.recv_timeout(Duration::from_secs(5)).unwrap());
+
+        let appender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            let mut file = fs::OpenOptions::new().append(true).open(&log).unwrap();
+            file.write_all(b"INFO still fine\nERROR disk full\n").unwrap();
+        });
+
+        assert_eq!("ERROR disk full\n", printed.recv_timeout(Duration::from_secs(5)).unwrap());
+
+        appender.join().unwrap();
+        stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        searcher.join().unwrap();
+    }
+
+    #[test]
+    fn build_checks_follow() {
+        assert!(Config::build(args(&["--follow", "ERROR", "app.log"])).unwrap().follow);
+        assert!(Config::build(args(&["--follow", "ERROR"])).is_err());
+    }
+
+    #[test]
+    fn build_rejects_in_place_without_replace() {
+        assert!(Config::build(args(&["--in-place", "rust", "poem.txt"])).is_err());
+        assert!(Config::build(args(&["--replace", "x", "--backup", ".bak", "a", "f"])).is_err());
+    }
+
+    fn index_fixture(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-indexed-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("poem.txt"), "I'm nobody! Who are you?\nThey'd banish us, you know.\n").unwrap();
+        fs::write(dir.join("docs/rust.md"), "Rust:\nsafe, fast, productive.\nTrust me.\n").unwrap();
+        fs::write(dir.join("docs/empty.txt"), "").unwrap();
+        dir
+    }
+
+    fn search_dir(config: &Config, dir: &Path, files: &[String]) -> String {
+        let set = if config.ignore_case {
+            PatternSet::new(config.patterns.iter().map(|p| p.to_lowercase()).collect())
+        } else {
+            PatternSet::new(config.patterns.clone())
+        };
+        let mut out = Vec::new();
+        search_files(config, &set, &config.patterns, dir, files, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // The index may only leave out files without matches, so searching the
+    // candidates prints exactly what searching every file prints.
+    fn assert_same_as_unindexed(dir: &Path, flags: &[&str]) {
+        let mut all = flags.to_vec();
+        let dir_arg = dir.to_str().unwrap();
+        all.extend(["--index", dir_arg]);
+        let config = Config::build(args(&all)).unwrap();
+
+        let (index, _) = index::Index::build(dir, None).unwrap();
+        let candidates = index
+            .candidates(dir, &config.patterns, config.ignore_case, config.fuzzy.is_none())
+            .unwrap();
+        let every_file = index::walk(dir).unwrap();
+
+        assert_eq!(search_dir(&config, dir, &every_file), search_dir(&config, dir, &candidates));
+    }
+
+    #[test]
+    fn indexed_search_matches_unindexed_search() {
+        let dir = index_fixture("same");
+
+        assert_same_as_unindexed(&dir, &["banish"]);
+        assert_same_as_unindexed(&dir, &["-e", "rust", "-e", "nobody"]);
+        assert_same_as_unindexed(&dir, &["-w", "me"]);
+        assert_same_as_unindexed(&dir, &["--json", "safe"]);
+        assert_same_as_unindexed(&dir, &["--fuzzy", "1", "banesh"]);
+        assert_same_as_unindexed(&dir, &["nowhere"]);
+    }
+
+    #[test]
+    fn indexed_matches_start_with_the_path() {
+        let dir = index_fixture("prefix");
+        let config = Config::build(args(&["--index", dir.to_str().unwrap(), "rust"])).unwrap();
+        let files = vec![String::from("docs/rust.md"), String::from("poem.txt")];
+
+        let rust_md = dir.join("docs/rust.md");
+        assert_eq!(format!("{}:Trust me.\n", rust_md.display()), search_dir(&config, &dir, &files));
+    }
+
+    #[test]
+    fn build_checks_index() {
+        let config = Config::build(args(&["--index", "src", "rust"])).unwrap();
+        assert_eq!(Some(String::from("src")), config.index_dir);
+
+        assert!(Config::build(args(&["--index", "src", "rust", "poem.txt"])).is_err());
+        assert!(Config::build(args(&["--index"])).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_the_config_file() {
+        let defaults = config_file::parse(
+            Path::new("config"),
+            "[flags]\n--encoding latin1\n--binary-files=without-match\n[color]\nwhen = always\n"
+        ).unwrap();
+        let mut all = vec!["--encoding", "utf-16le", "--color", "never", "rust", "poem.txt"];
+        all.splice(0..0, defaults.iter().map(String::as_str));
+
+        let config = Config::build(args(&all)).unwrap();
+
+        assert_eq!(Some(Encoding::Utf16Le), config.encoding);
+        assert_eq!(None, config.color);
+        // what the command line doesn't mention still comes from the file
+        assert_eq!(BinaryFiles::WithoutMatch, config.binary_files);
+        assert_eq!(vec!["rust"], config.patterns);
+    }
+
+    #[test]
+    fn command_line_turns_config_switches_off() {
+        let defaults = config_file::parse(Path::new("config"), "[flags]\n--column\n-w\n-a\n").unwrap();
+        let with_defaults = |command_line: &[&str]| {
+            let mut all: Vec<&str> = defaults.iter().map(String::as_str).collect();
+            all.extend(command_line);
+            Config::build(args(&all)).unwrap()
+        };
+
+        let config = with_defaults(&["rust", "poem.txt"]);
+        assert!(config.column);
+        assert_eq!(MatchMode::Word, config.mode);
+        assert_eq!(BinaryFiles::Text, config.binary_files);
+
+        let config = with_defaults(&["--no-column", "--no-word-regexp", "--no-text", "rust", "poem.txt"]);
+        assert!(!config.column);
+        assert_eq!(MatchMode::Substring, config.mode);
+        assert_eq!(BinaryFiles::Binary, config.binary_files);
+
+        // --no-text leaves a --binary-files choice alone
+        let config = Config::build(args(&["--binary-files=without-match", "--no-text", "a"])).unwrap();
+        assert_eq!(BinaryFiles::WithoutMatch, config.binary_files);
+    }
+
+    #[test]
+    fn build_reads_color_ignore_and_types() {
+        let config = Config::build(
+            args(
+                &[
+                    "--color",
+                    "always",
+                    "--colors",
+                    "match:green",
+                    "--ignore",
+                    "target",
+                    "--type-add",
+                    "web:*.{html,css},*.js",
+                    "--no-config",
+                    "rust",
+                ]
+            )
+        ).unwrap();
+
+        assert_eq!(Some(Colors { matched: "32", path: "35" }), config.color);
+        assert_eq!(vec![Glob::new("target").unwrap()], config.ignore);
+        assert!(config.types.globs("web").unwrap()[0].is_match("index.html"));
+
+        assert!(Config::build(args(&["--color", "sometimes", "a"])).is_err());
+        assert!(Config::build(args(&["--colors", "match:mauve", "a"])).is_err());
+        assert!(Config::build(args(&["--type-add", "web", "a"])).is_err());
+    }
+
+    #[test]
+    fn color_paints_the_matches() {
+        let config = Config::build(args(&["--color", "always", "-e", "fast", "-e", "safe"])).unwrap();
+
+        assert_eq!(
+            "\x1b[31msafe\x1b[0m, \x1b[31mfast\x1b[0m, productive.\n",
+            stream(&config, b"safe, fast, productive.\n")
+        );
+        // never when the output is JSON
+        assert_eq!(None, Config::build(args(&["--color", "always", "--json", "a"])).unwrap().color);
+    }
+
+    #[test]
+    fn indexed_search_leaves_out_ignored_files() {
+        let dir = index_fixture("ignore");
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("target/out.txt"), "Trust me.\n").unwrap();
+        index::Index::build(&dir, None).unwrap().0.save(&dir).unwrap();
+
+        let dir_arg = dir.to_str().unwrap();
+        let config = Config::build(args(&["--index", dir_arg, "rust"])).unwrap();
+        assert_eq!(
+            vec!["docs/rust.md", "target/out.txt"],
+            indexed_candidates(&config, &config.patterns, &dir).unwrap()
+        );
+
+        let config = Config::build(args(&["--ignore", "target", "--index", dir_arg, "rust"])).unwrap();
+        assert_eq!(vec!["docs/rust.md"], indexed_candidates(&config, &config.patterns, &dir).unwrap());
+    }
+
+    #[test]
+    fn build_reads_type_filters() {
+        let config = Config::build(args(&["-t", "web", "--type-add", "web:*.{html,css}", "a", "src"])).unwrap();
+        assert!(config.type_filter.allows("site/index.html"));
+        assert!(!config.type_filter.allows("src/main.rs"));
+
+        let config = Config::build(args(&["-T", "markdown", "-r", "a", "src"])).unwrap();
+        assert!(config.recursive);
+        assert!(!config.type_filter.allows("readme.md"));
+
+        assert!(Config::build(args(&["-t", "klingon", "a", "src"])).is_err());
+        assert!(Config::build(args(&["--type-list"])).unwrap().type_list);
+    }
+
+    #[test]
+    fn recursive_search_skips_other_types() {
+        let dir = index_fixture("types");
+        let dir_arg = dir.to_str().unwrap();
+        let config = Config::build(args(&["-r", "-t", "markdown", "rust", dir_arg])).unwrap();
+
+        let mut files = index::walk(&dir).unwrap();
+        files.retain(|file| wanted(&config, file));
+
+        assert_eq!(vec!["docs/rust.md"], files);
+    }
+
+    fn sorted_lines(text: String) -> Vec<String> {
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn threads_and_futures_find_the_same_lines() {
+        let dir = index_fixture("parallel");
+        for i in 0..20 {
+            fs::write(dir.join(format!("log{i}.txt")), format!("INFO {i}\nTrust {i}\n")).unwrap();
+        }
+        let files = index::walk(&dir).unwrap();
+        let dir_arg = dir.to_str().unwrap();
+
+        let config = Config::build(args(&["-r", "rust", dir_arg])).unwrap();
+        let one_by_one = sorted_lines(search_dir(&config, &dir, &files));
+        assert_eq!(21, one_by_one.len());
+
+        let config = Config::build(args(&["-r", "-j", "4", "rust", dir_arg])).unwrap();
+        assert_eq!(one_by_one, sorted_lines(search_dir(&config, &dir, &files)));
+
+        let config = Config::build(args(&["-r", "--async", "-j", "3", "rust", dir_arg])).unwrap();
+        assert_eq!(one_by_one, sorted_lines(search_dir(&config, &dir, &files)));
+    }
+
+    #[test]
+    fn build_checks_jobs() {
+        assert_eq!(Some(4), Config::build(args(&["-r", "-j", "4", "a", "src"])).unwrap().jobs);
+        assert!(Config::build(args(&["-r", "-j", "0", "a", "src"])).is_err());
+        assert!(Config::build(args(&["--async", "a", "poem.txt"])).is_err());
+    }
+
+    #[test]
+    fn case_insensitive_spans_point_into_the_original_line() {
+        // 'İ' is two bytes, but lowercases to "i̇", which is three
+        let patterns = PatternSet::new(vec![String::from("rust")]);
+
+        let found = find_in_line(&patterns, MatchMode::Substring, "İ RUST", true);
+
+        assert_eq!(vec![PatternMatch { pattern: 0, start: 3, end: 7 }], found);
+    }
+
+    #[test]
+    fn json_output_names_the_patterns() {
+        let patterns = vec![String::from("say \"hi\"")];
+        let hit = PatternHit { line_number: 7, line: "\tsay \"hi\"", patterns: vec![0] };
+
+        assert_eq!(
+            r#"{"line_number":7,"line":"\tsay \"hi\"","patterns":["say \"hi\""]}"#,
+            hit_to_json(&hit, &patterns)
+        );
     }
 }
 
@@ -0,0 +1,431 @@
+// Search and replace
+
+// `--replace` rewrites every match in a line. The matches come from the same
+// `find_in_line` the search uses, so -w, -x and IGNORE_CASE behave the same
+// when replacing.
+
+// The replacement can refer to what it replaces, the way regex replacements
+// refer to capture groups: `$0` (or `${0}`) is the whole match, and `$$` is a
+// '$'. The patterns are plain strings, not regexes, so the whole match is the
+// only group there is: `$1` and up are rejected by `check_replacement`. Any
+// other '$' is just a '$'.
+
+// `--in-place` writes the result back to the file. Writing straight into the
+// file would leave it half written if minigrep crashed (or the disk filled up)
+// in the middle, so we write a temporary file next to it and `rename` it over
+// the original: on the same filesystem a rename replaces the file in one step.
+// Each run (and each call) gets a temporary file of its own, so two runs on
+// the same file can't rename each other's half-written output into place.
+
+use std::{ fs, io::{ self, Write }, path::Path };
+
+use crate::temp_file;
+
+use super::{ MatchMode, find_in_line, leftmost_non_overlapping };
+use super::aho_corasick::PatternSet;
+
+const DIFF_CONTEXT: usize = 3;
+
+pub fn replace_line(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    ignore_case: bool,
+    line: &str,
+    replacement: &str
+) -> String {
+    let mut replaced = String::with_capacity(line.len());
+    let mut last = 0;
+
+    let found = find_in_line(patterns, mode, line, ignore_case);
+    for found in leftmost_non_overlapping(found) {
+        replaced.push_str(&line[last..found.start]);
+        expand(replacement, &line[found.start..found.end], &mut replaced);
+        last = found.end;
+    }
+    replaced.push_str(&line[last..]);
+
+    replaced
+}
+
+enum Reference {
+    Group(usize),
+    Dollar,
+}
+
+// What comes after a '$' and how many bytes of it belong to the reference.
+// None when it isn't one, and the '$' stays as it is.
+fn reference(after: &str) -> Option<(Reference, usize)> {
+    if after.starts_with('$') {
+        return Some((Reference::Dollar, 1));
+    }
+    if let Some(inside) = after.strip_prefix('{') {
+        let end = inside.find('}')?;
+        let group = inside[..end].parse().ok()?;
+        return Some((Reference::Group(group), end + 2));
+    }
+
+    let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+    let group = after[..digits].parse().ok()?;
+    Some((Reference::Group(group), digits))
+}
+
+// Appends `replacement` to `out`, with `matched` in place of every `$0`.
+fn expand(replacement: &str, matched: &str, out: &mut String) {
+    let mut rest = replacement;
+
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+
+        let used = match reference(after) {
+            Some((Reference::Group(0), used)) => {
+                out.push_str(matched);
+                used
+            }
+            Some((Reference::Dollar, used)) => {
+                out.push('$');
+                used
+            }
+            // check_replacement has turned these away already
+            Some((Reference::Group(_), used)) => used,
+            None => {
+                out.push('$');
+                0
+            }
+        };
+        rest = &after[used..];
+    }
+    out.push_str(rest);
+}
+
+// For `Config::build`: a replacement may only use the groups there are.
+pub fn check_replacement(replacement: &str) -> Result<(), &'static str> {
+    let mut rest = replacement;
+
+    while let Some(at) = rest.find('$') {
+        let after = &rest[at + 1..];
+        let used = match reference(after) {
+            Some((Reference::Group(group), _)) if group > 0 => {
+                return Err("--replace can only use $0, the whole match: patterns aren't regexes, so they have no capture groups");
+            }
+            Some((_, used)) => used,
+            None => 0,
+        };
+        rest = &after[used..];
+    }
+    Ok(())
+}
+
+// Replaces in every line of a whole file, keeping its line endings ("\n" or
+// "\r\n") untouched.
+pub fn replace_contents(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    ignore_case: bool,
+    contents: &str,
+    replacement: &str
+) -> String {
+    replace_lines(patterns, mode, ignore_case, contents, replacement).1.concat()
+}
+
+// The lines of `contents` with their line endings, and each one after the
+// replacement. --in-place writes the second list and --dry-run diffs the
+// two, so the diff is exactly what --in-place would do.
+pub fn replace_lines<'a>(
+    patterns: &PatternSet,
+    mode: MatchMode,
+    ignore_case: bool,
+    contents: &'a str,
+    replacement: &str
+) -> (Vec<&'a str>, Vec<String>) {
+    let old: Vec<&str> = contents.split_inclusive('\n').collect();
+    let new = old
+        .iter()
+        .map(|line| {
+            let text = line.trim_end_matches('\n').trim_end_matches('\r');
+            let ending = &line[text.len()..];
+            replace_line(patterns, mode, ignore_case, text, replacement) + ending
+        })
+        .collect();
+
+    (old, new)
+}
+
+// A unified diff (the `diff -u` / `git diff` format) of a line-for-line edit,
+// where `new[i]` is what `old[i]` was replaced with (a replacement containing
+// "\n" turns one line into several). The lines keep their endings, so a
+// "\r\n" survives `patch`, and a last line without one gets the usual
+// "\\ No newline at end of file".
+pub fn unified_diff(path: &str, old: &[&str], new: &[String]) -> String {
+    let changed: Vec<usize> = (0..old.len()).filter(|&i| old[i] != new[i]).collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- a/{path}\n+++ b/{path}\n");
+
+    // changes that are close enough for their context lines to touch go into
+    // the same hunk
+    let mut hunks: Vec<Vec<usize>> = Vec::new();
+    for &i in &changed {
+        match hunks.last_mut() {
+            Some(hunk) if i - hunk[hunk.len() - 1] <= 2 * DIFF_CONTEXT => hunk.push(i),
+            _ => hunks.push(vec![i]),
+        }
+    }
+
+    // how many more lines the new file has before the current hunk
+    let mut shift: isize = 0;
+
+    for hunk in hunks {
+        let first = hunk[0].saturating_sub(DIFF_CONTEXT);
+        let last = (hunk[hunk.len() - 1] + DIFF_CONTEXT).min(old.len() - 1);
+
+        let mut body = String::new();
+        let mut new_count = 0;
+        for i in first..=last {
+            if hunk.contains(&i) {
+                push_diff_line(&mut body, '-', old[i]);
+                for added in new[i].split_inclusive('\n') {
+                    push_diff_line(&mut body, '+', added);
+                    new_count += 1;
+                }
+            } else {
+                push_diff_line(&mut body, ' ', old[i]);
+                new_count += 1;
+            }
+        }
+
+        let old_count = last - first + 1;
+        let new_start = ((first as isize) + shift + 1) as usize;
+        diff.push_str(&format!("@@ -{},{} +{},{} @@\n", first + 1, old_count, new_start, new_count));
+        diff.push_str(&body);
+
+        shift += (new_count as isize) - (old_count as isize);
+    }
+
+    diff
+}
+
+fn push_diff_line(body: &mut String, marker: char, line: &str) {
+    body.push(marker);
+    body.push_str(line);
+    if !line.ends_with('\n') {
+        body.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+// Writes `contents` to `path` through a temporary file in the same directory
+// and a rename, optionally keeping a copy of the old file at `path + suffix`.
+pub fn write_atomically(path: &str, contents: &str, backup_suffix: Option<&str>) -> io::Result<()> {
+    let target = Path::new(path);
+    let (temp, mut file) = temp_file::create_next_to(target)?;
+
+    let result = (|| {
+        file.write_all(contents.as_bytes())?;
+        // make sure the bytes are on disk before the rename makes them visible
+        file.sync_all()?;
+        fs::set_permissions(&temp, fs::metadata(target)?.permissions())?;
+
+        if let Some(suffix) = backup_suffix {
+            fs::copy(target, format!("{path}{suffix}"))?;
+        }
+
+        fs::rename(&temp, target)
+    })();
+
+    if result.is_err() {
+        // don't leave the temporary file behind, the original is untouched
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn set(patterns: &[&str]) -> PatternSet {
+        PatternSet::new(
+            patterns
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        )
+    }
+
+    #[test]
+    fn replaces_every_occurrence() {
+        let patterns = set(&["fast", "safe"]);
+
+        assert_eq!(
+            "SPEEDY, SPEEDY, productive.",
+            replace_line(&patterns, MatchMode::Substring, false, "safe, fast, productive.", "SPEEDY")
+        );
+    }
+
+    #[test]
+    fn longest_pattern_wins_at_the_same_start() {
+        let patterns = set(&["he", "hers"]);
+
+        assert_eq!("us!", replace_line(&patterns, MatchMode::Substring, false, "ushers", "!"));
+    }
+
+    #[test]
+    fn replace_respects_word_mode_and_case() {
+        let patterns = set(&["rust"]);
+
+        assert_eq!(
+            "Ferris: trust Ferris",
+            replace_line(&patterns, MatchMode::Word, true, "RUST: trust Rust", "Ferris")
+        );
+    }
+
+    #[test]
+    fn replacement_can_use_the_match() {
+        let patterns = set(&["fast", "safe"]);
+        let line = "safe, fast, productive.";
+
+        assert_eq!("[safe], [fast], productive.", replace_line(&patterns, MatchMode::Substring, false, line, "[$0]"));
+        assert_eq!("safely, fastly, productive.", replace_line(&patterns, MatchMode::Substring, false, line, "${0}ly"));
+        assert_eq!("$0, $0, productive.", replace_line(&patterns, MatchMode::Substring, false, line, "$$0"));
+        assert_eq!("$x, $x, productive.", replace_line(&patterns, MatchMode::Substring, false, line, "$x"));
+        // case as it is in the line, not as in the pattern
+        assert_eq!("<Rust>", replace_line(&set(&["rust"]), MatchMode::Substring, true, "Rust", "<$0>"));
+    }
+
+    #[test]
+    fn only_the_whole_match_can_be_used() {
+        assert!(check_replacement("[$0] costs $$5, ${0}!").is_ok());
+        assert!(check_replacement("$ and $x and ${name}").is_ok());
+        assert!(check_replacement("$1").is_err());
+        assert!(check_replacement("x${2}").is_err());
+    }
+
+    #[test]
+    fn replace_contents_keeps_line_endings() {
+        let patterns = set(&["a"]);
+
+        assert_eq!(
+            "b\r\nxb\nb",
+            replace_contents(&patterns, MatchMode::Substring, false, "a\r\nxa\na", "b")
+        );
+    }
+
+    fn diff(patterns: &PatternSet, path: &str, original: &str, replacement: &str) -> String {
+        let (old, new) = replace_lines(patterns, MatchMode::Substring, false, original, replacement);
+
+        unified_diff(path, &old, &new)
+    }
+
+    #[test]
+    fn diff_shows_changed_lines_with_context() {
+        let original = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+        let patterns = set(&["two", "nine"]);
+
+        assert_eq!(
+            "\
+--- a/n.txt
++++ b/n.txt
+@@ -1,5 +1,5 @@
+ one
+-two
++2
+ three
+ four
+ five
+@@ -6,5 +6,5 @@
+ six
+ seven
+ eight
+-nine
++2
+ ten
+",
+            diff(&patterns, "n.txt", original, "2")
+        );
+    }
+
+    #[test]
+    fn diff_counts_lines_added_by_the_replacement() {
+        let original = "a\nb\n";
+        let patterns = set(&["a"]);
+
+        assert_eq!(
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,3 @@\n-a\n+x\n+y\n b\n",
+            diff(&patterns, "f", original, "x\ny")
+        );
+    }
+
+    #[test]
+    fn diff_keeps_crlf_endings() {
+        let original = "a\r\nb\r\n";
+        let patterns = set(&["a"]);
+
+        assert_eq!("--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n-a\r\n+x\r\n b\r\n", diff(&patterns, "f", original, "x"));
+    }
+
+    #[test]
+    fn diff_marks_a_missing_final_newline() {
+        let patterns = set(&["b"]);
+
+        assert_eq!(
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+x\n\\ No newline at end of file\n",
+            diff(&patterns, "f", "a\nb", "x")
+        );
+        // the context line at the end needs the marker too
+        assert_eq!(
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n-a\n+x\n b\n\\ No newline at end of file\n",
+            diff(&set(&["a"]), "f", "a\nb", "x")
+        );
+    }
+
+    #[test]
+    fn writes_atomically_with_a_backup() {
+        let dir = env::temp_dir().join(format!("minigrep-replace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "old contents\n").unwrap();
+
+        write_atomically(path, "new contents\n", Some(".bak")).unwrap();
+
+        assert_eq!("new contents\n", fs::read_to_string(path).unwrap());
+        assert_eq!("old contents\n", fs::read_to_string(format!("{path}.bak")).unwrap());
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writes_each_use_their_own_temp_file() {
+        let dir = env::temp_dir().join(format!("minigrep-replace-race-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "old contents\n").unwrap();
+        // a leftover from an older minigrep, or a crashed run
+        fs::write(dir.join(format!(".poem.txt.{}-0.tmp", std::process::id())), "stale\n").unwrap();
+
+        // every write is a whole file; a shared temporary file would mix them
+        let versions: Vec<String> = (0..8).map(|i| format!("{i}\n").repeat(10_000)).collect();
+        std::thread::scope(|scope| {
+            for version in &versions {
+                scope.spawn(move || {
+                    for _ in 0..5 {
+                        write_atomically(path, version, None).unwrap();
+                    }
+                });
+            }
+        });
+
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(versions.contains(&contents));
+        // the file and the leftover, nothing else
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
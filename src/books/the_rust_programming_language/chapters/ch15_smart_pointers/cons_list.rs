@@ -0,0 +1,378 @@
+// A cons list that can be used, not only built
+//
+// The `List` in mod.rs holds i32s and has to be put together by hand, with
+// nothing to read it back. This one has the same shape (a `Cons` holds a value
+// and a Box with the rest, `Nil` ends it), is generic, and has the methods and
+// traits that make it a collection: push and pop at the front, iterators,
+// `collect()`, and printing like Lisp does, `(1 2 3)`.
+//
+// Everything that walks the list does it with a loop, never by recursion, so a
+// list of a million elements can be compared, cloned, printed and dropped
+// without running out of stack.
+
+use std::{ fmt, iter::FusedIterator, mem };
+
+enum Link<T> {
+    Cons(T, Box<Link<T>>),
+    Nil,
+}
+
+use Link::{ Cons, Nil };
+
+pub struct List<T> {
+    head: Link<T>,
+    // kept up to date, so `len` doesn't have to walk the list
+    len: usize,
+}
+
+impl<T> List<T> {
+    pub fn new() -> List<T> {
+        List { head: Nil, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        let rest = mem::replace(&mut self.head, Nil);
+        self.head = Cons(value, Box::new(rest));
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        match mem::replace(&mut self.head, Nil) {
+            Cons(value, rest) => {
+                self.head = *rest;
+                self.len -= 1;
+                Some(value)
+            }
+            Nil => None,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        match &self.head {
+            Cons(value, _) => Some(value),
+            Nil => None,
+        }
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        match &mut self.head {
+            Cons(value, _) => Some(value),
+            Nil => None,
+        }
+    }
+
+    // Turns the list around in place: every node is moved to the front of
+    // the reversed part, nothing is copied or allocated.
+    pub fn reverse(&mut self) {
+        let mut rest = mem::replace(&mut self.head, Nil);
+        let mut reversed = Nil;
+
+        while let Cons(value, next) = rest {
+            rest = *next;
+            reversed = Cons(value, Box::new(reversed));
+        }
+        self.head = reversed;
+    }
+
+    // Moves everything in `other` to the end of this list, leaving `other`
+    // empty, like `Vec::append`.
+    pub fn append(&mut self, other: &mut List<T>) {
+        let mut tail = &mut self.head;
+        while let Cons(_, next) = tail {
+            tail = next;
+        }
+
+        *tail = mem::replace(&mut other.head, Nil);
+        self.len += mem::take(&mut other.len);
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: &self.head, left: self.len }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut { next: Some(&mut self.head), left: self.len }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> List<T> {
+        List::new()
+    }
+}
+
+// The derived Drop would drop the first Box, which drops the second, which
+// drops the third... one stack frame per element. Taking each rest out before
+// its node goes away keeps it to one at a time.
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut link = mem::replace(&mut self.head, Nil);
+        while let Cons(_, next) = &mut link {
+            link = mem::replace(next.as_mut(), Nil);
+        }
+    }
+}
+
+// By value: `for x in list`
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+// By reference: `for x in &list`
+pub struct Iter<'a, T> {
+    next: &'a Link<T>,
+    left: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match self.next {
+            Cons(value, rest) => {
+                self.next = rest;
+                self.left -= 1;
+                Some(value)
+            }
+            Nil => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+// By mutable reference: `for x in &mut list`
+pub struct IterMut<'a, T> {
+    // taken out while `next` runs: a `&mut` can't be copied like the `&` in
+    // Iter, only moved
+    next: Option<&'a mut Link<T>>,
+    left: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        match self.next.take()? {
+            Cons(value, rest) => {
+                self.next = Some(rest);
+                self.left -= 1;
+                Some(value)
+            }
+            Nil => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+impl<T> FusedIterator for IterMut<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+// Keeps the order: (1..=3).collect() is (1 2 3). Each value goes onto the
+// end, which is found once and then followed along.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> List<T> {
+        let mut list = List::new();
+        let mut tail = &mut list.head;
+
+        for value in iter {
+            *tail = Cons(value, Box::new(Nil));
+            if let Cons(_, next) = tail {
+                tail = next;
+            }
+            list.len += 1;
+        }
+        list
+    }
+}
+
+// (1 2 3), and () when empty
+impl<T: fmt::Display> fmt::Display for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{value}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+// by hand, like the rest: derived ones would recurse down the Boxes
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> List<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &List<T>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ cell::Cell, rc::Rc };
+
+    #[test]
+    fn push_pop_and_peek() {
+        let mut list = List::new();
+        assert_eq!(None, list.pop_front());
+        assert_eq!(None, list.peek());
+
+        list.push_front(3);
+        list.push_front(2);
+        list.push_front(1);
+        assert_eq!(3, list.len());
+        assert_eq!(Some(&1), list.peek());
+
+        if let Some(first) = list.peek_mut() {
+            *first = 10;
+        }
+        assert_eq!(Some(10), list.pop_front());
+        assert_eq!(Some(2), list.pop_front());
+        assert_eq!(1, list.len());
+        assert!(!list.is_empty());
+    }
+
+    #[test]
+    fn collect_keeps_the_order() {
+        let list: List<i32> = (1..=3).collect();
+
+        assert_eq!("(1 2 3)", list.to_string());
+        assert_eq!("()", List::<i32>::new().to_string());
+        assert_eq!("[1, 2, 3]", format!("{list:?}"));
+        assert_eq!(3, list.len());
+    }
+
+    #[test]
+    fn three_ways_to_iterate() {
+        let mut list: List<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+
+        for value in &mut list {
+            value.push('!');
+        }
+        let borrowed: Vec<&str> = (&list).into_iter().map(String::as_str).collect();
+        assert_eq!(vec!["a!", "b!", "c!"], borrowed);
+        assert_eq!(3, list.iter().len());
+
+        let owned: Vec<String> = list.into_iter().collect();
+        assert_eq!(vec!["a!", "b!", "c!"], owned);
+    }
+
+    #[test]
+    fn reverse_and_append() {
+        let mut list: List<i32> = (1..=3).collect();
+        list.reverse();
+        assert_eq!("(3 2 1)", list.to_string());
+
+        let mut more: List<i32> = (4..=5).collect();
+        list.append(&mut more);
+        assert_eq!("(3 2 1 4 5)", list.to_string());
+        assert_eq!(5, list.len());
+        assert!(more.is_empty());
+
+        let mut empty = List::new();
+        empty.append(&mut list);
+        assert_eq!(5, empty.len());
+        assert_eq!(empty, [3, 2, 1, 4, 5].into_iter().collect());
+    }
+
+    #[test]
+    fn a_million_elements_do_not_overflow_the_stack() {
+        let list: List<u32> = (0..1_000_000).collect();
+        let copy = list.clone();
+
+        assert_eq!(list, copy);
+        assert_eq!(Some(&0), list.peek());
+        drop(list);
+        drop(copy);
+    }
+
+    #[test]
+    fn every_value_is_dropped_once() {
+        // counts the drops of whatever holds a clone of it
+        struct Counted(Rc<Cell<usize>>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let mut list: List<Counted> = (0..10).map(|_| Counted(Rc::clone(&drops))).collect();
+        list.pop_front();
+        assert_eq!(1, drops.get());
+
+        drop(list);
+        assert_eq!(10, drops.get());
+    }
+}
@@ -0,0 +1,292 @@
+// Finding (and undoing) the cycle in box10
+//
+// Once `a` points at `b` and `b` points back at `a`, following the links never
+// ends: the derived Debug printed forever (until the stack ran out), and the
+// two nodes are never freed because each keeps the other's count above 0.
+//
+// A list can only go wrong in one way: at some node it loops back to one it
+// has already passed. Two pointers walking it at different speeds notice that
+// without remembering anything:
+//
+// - Floyd: one moves 1 step, the other 2; if there's a loop they land on the
+//   same node, if not the fast one reaches the end.
+// - Brent: one stays put while the other runs 1, 2, 4, 8... steps away from
+//   it, then catches up. Fewer steps, and it finds how long the loop is.
+//
+// "The same node" is Rc::ptr_eq: two different nodes can hold the same value.
+
+use std::{ collections::HashMap, fmt, rc::Rc };
+
+use super::List3::{ self, Cons, Nil };
+
+// A node of a singly linked list held together by Rc, like List3.
+pub trait Linked {
+    // the node this one links to, None at the end
+    fn next(&self) -> Option<Rc<Self>>;
+
+    // makes this node the end of the list
+    fn unlink(&self);
+}
+
+impl Linked for List3 {
+    // a Cons always links to something, at the end to a Nil node
+    fn next(&self) -> Option<Rc<List3>> {
+        self.tail().map(|link| Rc::clone(&link.borrow()))
+    }
+
+    fn unlink(&self) {
+        if let Some(link) = self.tail() {
+            *link.borrow_mut() = Rc::new(Nil);
+        }
+    }
+}
+
+// Floyd's tortoise and hare.
+pub fn has_cycle<N: Linked>(head: &Rc<N>) -> bool {
+    let mut slow = Rc::clone(head);
+    let mut fast = Rc::clone(head);
+
+    loop {
+        let Some(one_step) = fast.next() else {
+            return false;
+        };
+        let Some(two_steps) = one_step.next() else {
+            return false;
+        };
+        fast = two_steps;
+        // `slow` is on nodes `fast` has already been past, so it can't end
+        slow = slow.next().expect("the slow pointer is behind the fast one");
+
+        if Rc::ptr_eq(&slow, &fast) {
+            return true;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cycle {
+    // how many nodes come before the loop: the loop starts at node #start
+    pub start: usize,
+    // how many nodes are in the loop
+    pub len: usize,
+}
+
+// Brent's algorithm: where the loop starts and how long it is.
+pub fn find_cycle<N: Linked>(head: &Rc<N>) -> Option<Cycle> {
+    let mut power = 1;
+    let mut len = 1;
+    let mut tortoise = Rc::clone(head);
+    let mut hare = head.next()?;
+
+    while !Rc::ptr_eq(&tortoise, &hare) {
+        if power == len {
+            // catch up, and run twice as far this time
+            tortoise = Rc::clone(&hare);
+            power *= 2;
+            len = 0;
+        }
+        hare = hare.next()?;
+        len += 1;
+    }
+
+    // Start again with the hare `len` nodes ahead: they meet exactly where
+    // the loop begins, because from there the hare is a whole lap ahead.
+    let mut tortoise = Rc::clone(head);
+    let mut hare = Rc::clone(head);
+    for _ in 0..len {
+        hare = hare.next()?;
+    }
+    let mut start = 0;
+    while !Rc::ptr_eq(&tortoise, &hare) {
+        tortoise = tortoise.next()?;
+        hare = hare.next()?;
+        start += 1;
+    }
+
+    Some(Cycle { start, len })
+}
+
+// Ends the list at the last node of the loop, the one that links back, so
+// the nodes can be freed when nothing else holds them. A singly linked list
+// can loop only once, so that's every cycle there is. False if there was none.
+pub fn break_cycles<N: Linked>(head: &Rc<N>) -> bool {
+    let Some(cycle) = find_cycle(head) else {
+        return false;
+    };
+
+    let mut last = Rc::clone(head);
+    for _ in 1..cycle.start + cycle.len {
+        last = last.next().expect("the loop goes on");
+    }
+    last.unlink();
+    true
+}
+
+// Cons(5, Cons(10, Nil)), and for box10's a -> b -> a:
+// Cons(5, Cons(10, <cycle to #0>)), #0 being the first node printed.
+//
+// Nodes are told apart by their address; `self` isn't in an Rc here, so it's
+// compared as a plain pointer, which is what Rc::ptr_eq does as well.
+impl fmt::Debug for List3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut seen: HashMap<*const List3, usize> = HashMap::from([(self as *const List3, 0)]);
+        let mut open = 0;
+        let mut next = write_node(self, f, &mut open)?;
+
+        while let Some(node) = next {
+            let address = Rc::as_ptr(&node);
+            if let Some(number) = seen.get(&address) {
+                write!(f, "<cycle to #{number}>")?;
+                break;
+            }
+            seen.insert(address, seen.len());
+            next = write_node(&node, f, &mut open)?;
+        }
+
+        for _ in 0..open {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+// Writes the start of one node, and gives back the one after it, if any.
+fn write_node(node: &List3, f: &mut fmt::Formatter, open: &mut usize) -> Result<Option<Rc<List3>>, fmt::Error> {
+    match node {
+        Cons(value, link) => {
+            write!(f, "Cons({value:?}, ")?;
+            *open += 1;
+            Ok(Some(Rc::clone(&link.borrow())))
+        }
+        Nil => {
+            write!(f, "Nil")?;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ cell::{ Cell, RefCell }, rc::Weak };
+
+    fn cons(value: i32, next: &Rc<List3>) -> Rc<List3> {
+        Rc::new(Cons(value, RefCell::new(Rc::clone(next))))
+    }
+
+    // box10: a -> b -> a
+    fn box10() -> (Rc<List3>, Rc<List3>) {
+        let a = Rc::new(Cons(5, RefCell::new(Rc::new(Nil))));
+        let b = cons(10, &a);
+        if let Some(link) = a.tail() {
+            *link.borrow_mut() = Rc::clone(&b);
+        }
+        (a, b)
+    }
+
+    #[test]
+    fn lists_without_a_cycle() {
+        let nil = Rc::new(Nil);
+        let list = cons(10, &cons(5, &nil));
+
+        assert_eq!("Cons(10, Cons(5, Nil))", format!("{list:?}"));
+        assert!(!has_cycle(&list));
+        assert_eq!(None, find_cycle(&list));
+        assert!(!break_cycles(&list));
+        assert!(!has_cycle(&nil));
+    }
+
+    #[test]
+    fn box10_prints_its_cycle() {
+        let (a, b) = box10();
+
+        assert_eq!("Cons(5, Cons(10, <cycle to #0>))", format!("{a:?}"));
+        assert_eq!("Some(RefCell { value: Cons(10, Cons(5, <cycle to #0>)) })", format!("{:?}", a.tail()));
+        assert!(has_cycle(&a) && has_cycle(&b));
+        assert_eq!(Some(Cycle { start: 0, len: 2 }), find_cycle(&a));
+
+        break_cycles(&a);
+    }
+
+    #[test]
+    fn a_loop_further_down() {
+        // 1 -> 2 -> 3 -> 4 -> back to 2
+        let four = Rc::new(Cons(4, RefCell::new(Rc::new(Nil))));
+        let two = cons(2, &cons(3, &four));
+        let one = cons(1, &two);
+        if let Some(link) = four.tail() {
+            *link.borrow_mut() = Rc::clone(&two);
+        }
+
+        assert_eq!("Cons(1, Cons(2, Cons(3, Cons(4, <cycle to #1>))))", format!("{one:?}"));
+        assert_eq!(Some(Cycle { start: 1, len: 3 }), find_cycle(&one));
+
+        assert!(break_cycles(&one));
+        assert_eq!("Cons(1, Cons(2, Cons(3, Cons(4, Nil))))", format!("{one:?}"));
+        assert!(!has_cycle(&one));
+    }
+
+    #[test]
+    fn breaking_box10_frees_it() {
+        let (a, b) = box10();
+        let (weak_a, weak_b): (Weak<List3>, Weak<List3>) = (Rc::downgrade(&a), Rc::downgrade(&b));
+        assert_eq!((2, 2), (Rc::strong_count(&a), Rc::strong_count(&b)));
+
+        assert!(break_cycles(&a));
+        drop(a);
+        drop(b);
+
+        assert!(weak_a.upgrade().is_none() && weak_b.upgrade().is_none());
+    }
+
+    // A List3-style node that counts how many of its kind were dropped.
+    struct Tracked {
+        next: RefCell<Option<Rc<Tracked>>>,
+        drops: Rc<Cell<usize>>,
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    impl Linked for Tracked {
+        fn next(&self) -> Option<Rc<Tracked>> {
+            self.next.borrow().clone()
+        }
+
+        fn unlink(&self) {
+            *self.next.borrow_mut() = None;
+        }
+    }
+
+    // `len` nodes in a ring, the first one returned
+    fn ring(len: usize, drops: &Rc<Cell<usize>>) -> Rc<Tracked> {
+        let first = Rc::new(Tracked { next: RefCell::new(None), drops: Rc::clone(drops) });
+        let mut last = Rc::clone(&first);
+        for _ in 1..len {
+            let node = Rc::new(Tracked { next: RefCell::new(None), drops: Rc::clone(drops) });
+            *last.next.borrow_mut() = Some(Rc::clone(&node));
+            last = node;
+        }
+        *last.next.borrow_mut() = Some(Rc::clone(&first));
+        first
+    }
+
+    #[test]
+    fn a_ring_leaks_until_it_is_broken() {
+        let drops = Rc::new(Cell::new(0));
+
+        let leaked = ring(3, &drops);
+        assert!(has_cycle(&leaked));
+        drop(leaked);
+        assert_eq!(0, drops.get());
+
+        let freed = ring(5, &drops);
+        assert_eq!(Some(Cycle { start: 0, len: 5 }), find_cycle(&freed));
+        assert!(break_cycles(&freed));
+        drop(freed);
+        assert_eq!(5, drops.get());
+    }
+}
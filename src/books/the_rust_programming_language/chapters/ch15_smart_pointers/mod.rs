@@ -18,6 +18,11 @@
 // When you have a large amount of data and you want to transfer ownership but ensure the data won’t be copied when you do so
 // When you want to own a value and you care only that it’s a type that implements a particular trait rather than being of a specific type
 
+mod cons_list;
+mod cycles;
+mod rc_list;
+mod tree;
+
 use std::{ cell::RefCell, ops::Deref, rc::{ Rc, Weak } };
 
 // Using Box<T> to Store Data on the Heap
@@ -49,6 +54,9 @@ pub fn box2() {
     let list = Cons(1, Box::new(Cons(2, Box::new(Cons(3, Box::new(Nil))))));
 }
 
+// cons_list.rs has a generic List<T> of the same shape, with push/pop,
+// iterators and collect(): let list: List<i32> = (1..=3).collect();
+
 // Treating Smart Pointers Like Regular References with Deref
 // Implementing the Deref trait allows you to customize the behavior of the dereference operator *
 
@@ -217,6 +225,9 @@ pub fn box9() {
 // count after creating c = 3
 // count after c goes out of scope = 2
 
+// rc_list.rs turns this into RcList<T>, a list that is never changed: prepend
+// gives a new list that shares the old one, the way b and c share a here.
+
 // RefCell<T> and the Interior Mutability Pattern
 // Interior mutability is a design pattern in Rust that allows you to mutate data even when there are immutable references to that data; normally, this action is disallowed by the borrowing rules. To mutate data, the pattern uses unsafe code inside a data structure to bend Rust’s usual rules that govern mutation and borrowing.
 
@@ -301,7 +312,8 @@ mod tests {
 // Reference Cycles Can Leak Memory
 // Rust’s memory safety guarantees make it difficult, but not impossible, to accidentally create memory that is never cleaned up (known as a memory leak). Preventing memory leaks entirely is not one of Rust’s guarantees, meaning memory leaks are memory safe in Rust. We can see that Rust allows memory leaks by using Rc<T> and RefCell<T>: it’s possible to create references where items refer to each other in a cycle. This creates memory leaks because the reference count of each item in the cycle will never reach 0, and the values will never be dropped.
 
-#[derive(Debug)]
+// Debug is written by hand in cycles.rs: the derived one would follow a cycle
+// forever
 enum List3 {
     Cons(i32, RefCell<Rc<List3>>),
     Nil,
@@ -368,15 +380,27 @@ pub fn box10() {
 
     // ❌ Uncommenting the below line will cause infinite recursion
     // because Rust will keep traversing a → b → a → b → ...
+
+    // ✅ With the Debug from cycles.rs it stops where the cycle starts:
+    println!("a next item = {:?}", a.tail());
+    println!("a has a cycle: {}", cycles::has_cycle(&a));
+
+    // 🔧 Pointing the last link of the cycle back at Nil lets both be freed
+    // when a and b go out of scope
+    cycles::break_cycles(&a);
+    println!("a rc count after breaking the cycle = {}", Rc::strong_count(&a)); // → 1
 }
 
 // a initial rc count = 1
 // a next item = Some(RefCell { value: Nil })
 // a rc count after b creation = 2
 // b initial rc count = 1
-// b next item = Some(RefCell { value: Cons(5, RefCell { value: Nil }) })
+// b next item = Some(RefCell { value: Cons(5, Nil) })
 // b rc count after changing a = 2
 // a rc count after changing a = 2
+// a next item = Some(RefCell { value: Cons(10, Cons(5, <cycle to #0>)) })
+// a has a cycle: true
+// a rc count after breaking the cycle = 1
 
 // 🧠 Rc<T> + Weak<T> — Preventing Reference Cycles
 //
@@ -427,10 +451,11 @@ pub fn box11() {
 
 // Adding a Reference from a Child to Its Parent
 #[derive(Debug)]
-struct Node2 {
-    value: i32,
-    parent: RefCell<Weak<Node2>>,
-    children: RefCell<Vec<Rc<Node2>>>,
+// generic for tree.rs, i32 unless said otherwise
+struct Node2<T = i32> {
+    value: T,
+    parent: RefCell<Weak<Node2<T>>>,
+    children: RefCell<Vec<Rc<Node2<T>>>>,
 }
 
 pub fn box12() {
@@ -455,3 +480,8 @@ pub fn box12() {
 
 // leaf parent = None
 // leaf parent = Some(Node2 { value: 5, parent: RefCell { value: (Weak) }, children: RefCell { value: [Node2 { value: 3, parent: RefCell { value: (Weak) }, children: RefCell { value: [] } }] } })
+
+// tree.rs wraps Node2 in Tree<T>, which sets both sides of a link at once:
+// branch.add_child(&leaf) does what the two steps above do, and there are
+// walks over the whole tree (pre-order, post-order, level by level), find,
+// depth, and taking a subtree out with detach().
@@ -0,0 +1,335 @@
+// A list that is never changed, only built upon
+//
+// `box8` and `box9` share `a` between `b` and `c` with Rc::clone, which is all
+// a persistent list is about: `prepend` doesn't touch the list it's called
+// on, it makes one new node that points at it. The old list is still there,
+// unchanged, for whoever holds it, and the two share every node but the new
+// one. Cloning the whole list is one Rc::clone.
+//
+//   let a = (5 10)        a ──> 5 ──> 10
+//   let b = a.prepend(3)  b ──> 3 ──┘
+//   let c = a.prepend(4)  c ──> 4 ──┘
+//
+// `Queue` is two of them: one to take from the front of, one to add to the
+// back of, kept backwards.
+
+use std::{ fmt, rc::Rc };
+
+struct Node<T> {
+    value: T,
+    next: Option<Rc<Node<T>>>,
+}
+
+pub struct RcList<T> {
+    head: Option<Rc<Node<T>>>,
+}
+
+impl<T> RcList<T> {
+    pub fn new() -> RcList<T> {
+        RcList { head: None }
+    }
+
+    // A new list, `value` followed by this one. This one isn't changed.
+    pub fn prepend(&self, value: T) -> RcList<T> {
+        RcList { head: Some(Rc::new(Node { value, next: self.head.clone() })) }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    // Everything after the head, shared with this list. None when empty.
+    pub fn tail(&self) -> Option<RcList<T>> {
+        self.head.as_ref().map(|node| RcList { head: node.next.clone() })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    // walks the list, there is no count to keep up to date
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: self.head.as_deref() }
+    }
+
+    pub fn reversed(&self) -> RcList<T> where T: Clone {
+        let mut reversed = RcList::new();
+        for value in self {
+            reversed = reversed.prepend(value.clone());
+        }
+        reversed
+    }
+}
+
+impl<T> Default for RcList<T> {
+    fn default() -> RcList<T> {
+        RcList::new()
+    }
+}
+
+// Cheap whatever T is: only the first node's count goes up.
+impl<T> Clone for RcList<T> {
+    fn clone(&self) -> RcList<T> {
+        RcList { head: self.head.clone() }
+    }
+}
+
+// A node can only be dropped by the last list that holds it. Going down the
+// list until a node that someone else still holds, one node at a time,
+// instead of letting each drop the next and use up the stack.
+impl<T> Drop for RcList<T> {
+    fn drop(&mut self) {
+        let mut next = self.head.take();
+        while let Some(node) = next {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => {
+                    next = node.next.take();
+                }
+                // shared from here on, it's the other list's to drop
+                Err(_) => {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(&node.value)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a RcList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+// In the order given: (1..=3).collect() is (1 2 3).
+impl<T> FromIterator<T> for RcList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> RcList<T> {
+        let values: Vec<T> = iter.into_iter().collect();
+
+        let mut list = RcList::new();
+        for value in values.into_iter().rev() {
+            list = list.prepend(value);
+        }
+        list
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for RcList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{value}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RcList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for RcList<T> {
+    fn eq(&self, other: &RcList<T>) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+// First in, first out, and just as persistent: `push_back` and `pop_front`
+// give a new queue and leave this one as it was.
+//
+// `front` is read from the start, `back` holds the newest values first. When
+// `front` runs out, `back` is turned around to become the new `front`. That
+// copies the values (so T: Clone), but every value is copied at most once on
+// its way through a queue that's used in a line; going back to old versions
+// and popping them again can make it do the copying more than once.
+pub struct Queue<T> {
+    front: RcList<T>,
+    back: RcList<T>,
+}
+
+impl<T: Clone> Queue<T> {
+    pub fn new() -> Queue<T> {
+        Queue { front: RcList::new(), back: RcList::new() }
+    }
+
+    // Keeps `front` from being empty while there is anything in `back`, so
+    // `peek` never has to turn anything around.
+    fn balanced(front: RcList<T>, back: RcList<T>) -> Queue<T> {
+        if front.is_empty() {
+            Queue { front: back.reversed(), back: RcList::new() }
+        } else {
+            Queue { front, back }
+        }
+    }
+
+    pub fn push_back(&self, value: T) -> Queue<T> {
+        Queue::balanced(self.front.clone(), self.back.prepend(value))
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.front.head()
+    }
+
+    // The first value, and the queue without it.
+    pub fn pop_front(&self) -> Option<(&T, Queue<T>)> {
+        let first = self.front.head()?;
+        let rest = self.front.tail()?;
+        Some((first, Queue::balanced(rest, self.back.clone())))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+
+    // Front to back.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let back: Vec<&T> = self.back.iter().collect();
+        self.front.iter().chain(back.into_iter().rev())
+    }
+}
+
+impl<T: Clone> Default for Queue<T> {
+    fn default() -> Queue<T> {
+        Queue::new()
+    }
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Queue<T> {
+        Queue { front: self.front.clone(), back: self.back.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // how many lists hold the first node
+    fn count<T>(list: &RcList<T>) -> usize {
+        list.head.as_ref().map_or(0, Rc::strong_count)
+    }
+
+    #[test]
+    fn prepend_shares_the_tail() {
+        // box9 again, with RcList
+        let a: RcList<i32> = [5, 10].into_iter().collect();
+        assert_eq!(1, count(&a));
+
+        let b = a.prepend(3);
+        assert_eq!(2, count(&a));
+        {
+            let c = a.prepend(4);
+            assert_eq!(3, count(&a));
+            assert_eq!("(4 5 10)", c.to_string());
+        }
+        assert_eq!(2, count(&a));
+
+        // `b`'s tail is `a` itself, not a copy
+        let tail = b.tail().unwrap();
+        assert!(Rc::ptr_eq(tail.head.as_ref().unwrap(), a.head.as_ref().unwrap()));
+        assert_eq!(3, count(&a));
+    }
+
+    #[test]
+    fn old_versions_stay_valid() {
+        let empty = RcList::new();
+        let one = empty.prepend(1);
+        let two = one.prepend(2);
+        let other = one.prepend(20);
+        drop(two.clone());
+
+        assert_eq!("()", empty.to_string());
+        assert_eq!("(1)", one.to_string());
+        assert_eq!("(2 1)", two.to_string());
+        assert_eq!("(20 1)", other.to_string());
+        assert_eq!(Some(&2), two.head());
+        assert_eq!(Some(RcList::new()), one.tail());
+        assert_eq!(None, empty.tail());
+        assert_eq!(2, other.len());
+    }
+
+    #[test]
+    fn dropping_a_version_frees_only_its_own_nodes() {
+        let shared: RcList<i32> = (1..=3).collect();
+        let longer = shared.prepend(0);
+        assert_eq!(2, count(&shared));
+
+        drop(longer);
+
+        assert_eq!(1, count(&shared));
+        assert_eq!("(1 2 3)", shared.to_string());
+    }
+
+    #[test]
+    fn long_lists_drop_without_recursing() {
+        let list: RcList<u32> = (0..500_000).collect();
+        let shared = list.tail().unwrap();
+
+        drop(list);
+        assert_eq!(499_999, shared.len());
+    }
+
+    #[test]
+    fn queue_is_first_in_first_out() {
+        let queue = Queue::new().push_back(1).push_back(2).push_back(3);
+        assert_eq!(vec![&1, &2, &3], queue.iter().collect::<Vec<_>>());
+
+        let (first, rest) = queue.pop_front().unwrap();
+        assert_eq!(1, *first);
+        let rest = rest.push_back(4);
+        let (second, rest) = rest.pop_front().unwrap();
+        assert_eq!(2, *second);
+        assert_eq!(vec![&3, &4], rest.iter().collect::<Vec<_>>());
+
+        // the queue we started from is still all there
+        assert_eq!(3, queue.len());
+        assert_eq!(Some(&1), queue.peek());
+    }
+
+    #[test]
+    fn queue_versions_share_their_lists() {
+        let queue = Queue::new().push_back('a').push_back('b').push_back('c');
+        // taking 'a' turns (c b) around into a new front, (b c)
+        let (_, without_a) = queue.pop_front().unwrap();
+        let (_, without_b) = without_a.pop_front().unwrap();
+
+        // taking 'b' only moves one node further into that same front
+        let second_node = without_a.front.head.as_ref().unwrap().next.as_ref().unwrap();
+        assert!(Rc::ptr_eq(second_node, without_b.front.head.as_ref().unwrap()));
+        assert_eq!(2, Rc::strong_count(second_node));
+
+        let (_, emptied) = without_b.pop_front().unwrap();
+        assert!(emptied.is_empty() && emptied.pop_front().is_none());
+        assert_eq!(Some(&'b'), without_a.peek());
+        assert_eq!(Some(&'a'), queue.peek());
+    }
+}
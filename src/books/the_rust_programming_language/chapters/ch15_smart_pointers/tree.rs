@@ -0,0 +1,362 @@
+// A tree that keeps its own parent links
+//
+// `box12` shows the idea: a parent owns its children (Rc), a child only knows
+// its parent (Weak), so there's no cycle and dropping the root frees
+// everything. But both sides of every link are set by hand, and forgetting
+// one leaves a child that thinks it belongs to a node that doesn't have it.
+//
+// `Tree<T>` is a handle to one `Node2` (and so to the subtree under it).
+// `add_child` and `remove_child` change both sides of the link at once, and
+// everything that goes up the tree does it through `upgrade()`, so a parent
+// that's gone is simply not there.
+//
+// The walks hand out `Tree`s rather than `&T`s: the children are behind a
+// RefCell, so a reference into them couldn't outlive the borrow. Cloning a
+// `Tree` is one Rc::clone.
+
+use std::{ cell::RefCell, collections::VecDeque, fmt, rc::{ Rc, Weak } };
+
+use super::Node2;
+
+pub struct Tree<T> {
+    node: Rc<Node2<T>>,
+}
+
+impl<T> Tree<T> {
+    // A node on its own, no parent and no children.
+    pub fn new(value: T) -> Tree<T> {
+        Tree {
+            node: Rc::new(Node2 {
+                value,
+                parent: RefCell::new(Weak::new()),
+                children: RefCell::new(vec![]),
+            }),
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.node.value
+    }
+
+    // Whether the two are the same node, not just equal values.
+    pub fn ptr_eq(&self, other: &Tree<T>) -> bool {
+        Rc::ptr_eq(&self.node, &other.node)
+    }
+
+    pub fn parent(&self) -> Option<Tree<T>> {
+        self.node.parent.borrow().upgrade().map(|node| Tree { node })
+    }
+
+    pub fn children(&self) -> Vec<Tree<T>> {
+        self.node.children.borrow().iter().map(|node| Tree { node: Rc::clone(node) }).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.parent().is_none()
+    }
+
+    // Puts `child` last among this node's children, taking it away from its
+    // old parent first if it had one.
+    //
+    // Panics if `child` is this node or one above it: the tree would own
+    // itself, and never be freed.
+    pub fn add_child(&self, child: &Tree<T>) {
+        assert!(
+            !child.ptr_eq(self) && !self.ancestors().any(|ancestor| ancestor.ptr_eq(child)),
+            "a node can't be added under itself"
+        );
+
+        child.detach();
+        *child.node.parent.borrow_mut() = Rc::downgrade(&self.node);
+        self.node.children.borrow_mut().push(Rc::clone(&child.node));
+    }
+
+    // A new node for `value`, added as the last child.
+    pub fn push_child(&self, value: T) -> Tree<T> {
+        let child = Tree::new(value);
+        self.add_child(&child);
+        child
+    }
+
+    // Takes `child` out of this node's children; it keeps its own subtree and
+    // becomes a root. False if it wasn't a child of this node.
+    pub fn remove_child(&self, child: &Tree<T>) -> bool {
+        let mut children = self.node.children.borrow_mut();
+        let Some(index) = children.iter().position(|node| Rc::ptr_eq(node, &child.node)) else {
+            return false;
+        };
+
+        children.remove(index);
+        *child.node.parent.borrow_mut() = Weak::new();
+        true
+    }
+
+    // Takes this subtree out of the tree it's in. Nothing happens to a root.
+    pub fn detach(&self) {
+        if let Some(parent) = self.parent() {
+            parent.remove_child(self);
+        }
+    }
+
+    // The parent, its parent, and so on up to the root.
+    pub fn ancestors(&self) -> Ancestors<T> {
+        Ancestors { next: self.node.parent.borrow().upgrade() }
+    }
+
+    // 0 for a root.
+    pub fn depth(&self) -> usize {
+        self.ancestors().count()
+    }
+
+    // The top of the tree this node is in.
+    pub fn root(&self) -> Tree<T> {
+        self.ancestors().last().unwrap_or_else(|| self.clone())
+    }
+
+    // Every node under this one, itself included, each parent before its
+    // children: the order the tree would be written down in.
+    pub fn pre_order(&self) -> PreOrder<T> {
+        PreOrder { stack: vec![Rc::clone(&self.node)] }
+    }
+
+    // Each parent after its children: the order to free or sum things up in.
+    pub fn post_order(&self) -> PostOrder<T> {
+        PostOrder { stack: vec![(Rc::clone(&self.node), false)] }
+    }
+
+    // Level by level: this node, then its children, then theirs...
+    pub fn breadth_first(&self) -> BreadthFirst<T> {
+        BreadthFirst { queue: VecDeque::from([Rc::clone(&self.node)]) }
+    }
+
+    // The first node, in pre-order, whose value matches.
+    pub fn find(&self, mut predicate: impl FnMut(&T) -> bool) -> Option<Tree<T>> {
+        self.pre_order().find(|tree| predicate(tree.value()))
+    }
+}
+
+impl<T> Clone for Tree<T> {
+    fn clone(&self) -> Tree<T> {
+        Tree { node: Rc::clone(&self.node) }
+    }
+}
+
+// The subtree as nested values: Tree(5, [Tree(3, [])]). The parent is left
+// out, printing it would go back up and around again.
+impl<T: fmt::Debug> fmt::Debug for Tree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Tree").field(self.value()).field(&self.children()).finish()
+    }
+}
+
+pub struct Ancestors<T> {
+    next: Option<Rc<Node2<T>>>,
+}
+
+impl<T> Iterator for Ancestors<T> {
+    type Item = Tree<T>;
+
+    fn next(&mut self) -> Option<Tree<T>> {
+        let node = self.next.take()?;
+        self.next = node.parent.borrow().upgrade();
+        Some(Tree { node })
+    }
+}
+
+pub struct PreOrder<T> {
+    stack: Vec<Rc<Node2<T>>>,
+}
+
+impl<T> Iterator for PreOrder<T> {
+    type Item = Tree<T>;
+
+    fn next(&mut self) -> Option<Tree<T>> {
+        let node = self.stack.pop()?;
+        // backwards, so the first child is the next one popped
+        self.stack.extend(node.children.borrow().iter().rev().cloned());
+        Some(Tree { node })
+    }
+}
+
+pub struct PostOrder<T> {
+    // each node with whether its children have been put on the stack yet
+    stack: Vec<(Rc<Node2<T>>, bool)>,
+}
+
+impl<T> Iterator for PostOrder<T> {
+    type Item = Tree<T>;
+
+    fn next(&mut self) -> Option<Tree<T>> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some(Tree { node });
+            }
+
+            // seen again once everything above it on the stack is done
+            let children: Vec<Rc<Node2<T>>> = node.children.borrow().iter().rev().cloned().collect();
+            self.stack.push((node, true));
+            self.stack.extend(children.into_iter().map(|child| (child, false)));
+        }
+    }
+}
+
+pub struct BreadthFirst<T> {
+    queue: VecDeque<Rc<Node2<T>>>,
+}
+
+impl<T> Iterator for BreadthFirst<T> {
+    type Item = Tree<T>;
+
+    fn next(&mut self) -> Option<Tree<T>> {
+        let node = self.queue.pop_front()?;
+        self.queue.extend(node.children.borrow().iter().cloned());
+        Some(Tree { node })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //        1
+    //      /   \
+    //     2     3
+    //    / \     \
+    //   4   5     6
+    fn sample() -> (Tree<i32>, Vec<Tree<i32>>) {
+        let root = Tree::new(1);
+        let two = root.push_child(2);
+        let three = root.push_child(3);
+        let four = two.push_child(4);
+        let five = two.push_child(5);
+        let six = three.push_child(6);
+        (root.clone(), vec![root, two, three, four, five, six])
+    }
+
+    fn values(trees: impl Iterator<Item = Tree<i32>>) -> Vec<i32> {
+        trees.map(|tree| *tree.value()).collect()
+    }
+
+    #[test]
+    fn add_child_sets_both_links() {
+        let branch = Tree::new(5);
+        let leaf = Tree::new(3);
+        assert!(leaf.parent().is_none());
+
+        branch.add_child(&leaf);
+
+        assert!(leaf.parent().unwrap().ptr_eq(&branch));
+        assert!(branch.children()[0].ptr_eq(&leaf));
+        assert_eq!("Tree(5, [Tree(3, [])])", format!("{branch:?}"));
+    }
+
+    #[test]
+    fn walking_the_tree() {
+        let (root, nodes) = sample();
+
+        assert_eq!(vec![1, 2, 4, 5, 3, 6], values(root.pre_order()));
+        assert_eq!(vec![4, 5, 2, 6, 3, 1], values(root.post_order()));
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], values(root.breadth_first()));
+        assert_eq!(vec![2, 4, 5], values(nodes[1].pre_order()));
+
+        assert_eq!(vec![3, 1], values(nodes[5].ancestors()));
+        assert_eq!(vec![0, 1, 1, 2, 2, 2], nodes.iter().map(Tree::depth).collect::<Vec<_>>());
+        assert!(nodes[4].root().ptr_eq(&root));
+    }
+
+    #[test]
+    fn find_by_value() {
+        let (root, nodes) = sample();
+
+        // pre-order: 5 is reached before 3
+        assert!(root.find(|value| value % 2 == 1 && *value > 1).unwrap().ptr_eq(&nodes[4]));
+        assert!(nodes[1].find(|value| *value == 6).is_none());
+        assert!(root.find(|value| *value == 6).unwrap().ptr_eq(&nodes[5]));
+    }
+
+    #[test]
+    fn moving_and_detaching_subtrees() {
+        let (root, nodes) = sample();
+        let (two, three, six) = (&nodes[1], &nodes[2], &nodes[5]);
+
+        // 2 and what's under it go from the root to 6
+        six.add_child(two);
+        assert_eq!(vec![1, 3, 6, 2, 4, 5], values(root.pre_order()));
+        assert!(two.parent().unwrap().ptr_eq(six));
+        assert_eq!(4, nodes[3].depth());
+
+        three.detach();
+        assert!(three.is_root());
+        assert_eq!(vec![1], values(root.pre_order()));
+        assert_eq!(vec![3, 6, 2, 4, 5], values(three.pre_order()));
+
+        assert!(!root.remove_child(three));
+        three.detach();
+        assert!(three.is_root());
+    }
+
+    #[test]
+    #[should_panic(expected = "a node can't be added under itself")]
+    fn a_node_cannot_own_its_ancestor() {
+        let (root, nodes) = sample();
+        nodes[3].add_child(&root);
+    }
+
+    #[test]
+    fn counts_go_back_down() {
+        let branch = Tree::new(5);
+        let leaf = Tree::new(3);
+        let baseline = (Rc::strong_count(&leaf.node), Rc::weak_count(&branch.node));
+        assert_eq!((1, 0), baseline);
+
+        branch.add_child(&leaf);
+        // the branch owns the leaf, the leaf only points at the branch
+        assert_eq!(2, Rc::strong_count(&leaf.node));
+        assert_eq!(1, Rc::weak_count(&branch.node));
+        assert_eq!(1, Rc::strong_count(&branch.node));
+
+        assert!(branch.remove_child(&leaf));
+        assert_eq!(baseline, (Rc::strong_count(&leaf.node), Rc::weak_count(&branch.node)));
+    }
+
+    #[test]
+    fn dropping_the_handles_frees_every_node() {
+        let (root, nodes) = sample();
+        let weak: Vec<Weak<Node2<i32>>> = nodes.iter().map(|tree| Rc::downgrade(&tree.node)).collect();
+
+        // walks hold handles only while they're being used
+        let _ = root.post_order().count();
+        assert_eq!(2, Rc::strong_count(&nodes[3].node));
+
+        drop(root);
+        drop(nodes);
+
+        assert!(weak.iter().all(|node| node.strong_count() == 0 && node.upgrade().is_none()));
+    }
+
+    #[test]
+    fn a_kept_subtree_outlives_the_dropped_tree() {
+        // the subtree comes first: its own leaves point up at it
+        let branch = Tree::new(2);
+        let leaf = branch.push_child(4);
+        branch.push_child(5);
+        let counts = |tree: &Tree<i32>| (Rc::strong_count(&tree.node), Rc::weak_count(&tree.node));
+        let (branch_baseline, leaf_baseline) = (counts(&branch), counts(&leaf));
+        assert_eq!((1, 2), branch_baseline);
+
+        // then the tree around it
+        let root = Tree::new(1);
+        root.add_child(&branch);
+        root.push_child(3).push_child(6);
+        let gone = Rc::downgrade(&root.node);
+        assert_eq!((2, 2), counts(&branch));
+
+        drop(root);
+
+        assert!(gone.upgrade().is_none());
+        assert!(branch.is_root());
+        assert_eq!(branch_baseline, counts(&branch));
+        assert_eq!(leaf_baseline, counts(&leaf));
+    }
+}
@@ -0,0 +1,169 @@
+use rand::Rng;
+use std::{cmp::Ordering, ops::RangeInclusive};
+
+// How a game is played: which numbers the secret can be, how many guesses
+// the player gets (None is unlimited) and whether to show the secret.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub range: RangeInclusive<u32>,
+    pub max_attempts: Option<u32>,
+    pub debug: bool,
+}
+
+// The rules of the game, without any reading or printing. Whoever drives it
+// (the terminal in `guess()`, a test, a solver) hands in guesses and gets
+// back what happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuessingGame {
+    settings: Settings,
+    secret: u32,
+    attempts: u32,
+    over: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuessOutcome {
+    // `attempts_left` is None when attempts are unlimited
+    TooSmall { attempts_left: Option<u32> },
+    TooBig { attempts_left: Option<u32> },
+    Won { attempts: u32 },
+    // the last attempt was used up without finding the secret
+    Lost { secret: u32 },
+    // outside the range: doesn't use up an attempt
+    OutOfRange { low: u32, high: u32 },
+    // the game already ended with Won or Lost
+    GameOver,
+}
+
+impl GuessingGame {
+    // The secret comes from `rng`, so a test can pass a seeded one and know
+    // what the game will pick.
+    pub fn new(settings: &Settings, rng: &mut impl Rng) -> GuessingGame {
+        let secret = rng.gen_range(settings.range.clone());
+        GuessingGame::with_secret(settings, secret)
+    }
+
+    pub fn with_secret(settings: &Settings, secret: u32) -> GuessingGame {
+        GuessingGame {
+            settings: settings.clone(),
+            secret,
+            attempts: 0,
+            over: false,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn range(&self) -> &RangeInclusive<u32> {
+        &self.settings.range
+    }
+
+    pub fn max_attempts(&self) -> Option<u32> {
+        self.settings.max_attempts
+    }
+
+    pub fn secret(&self) -> u32 {
+        self.secret
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    pub fn guess(&mut self, guess: u32) -> GuessOutcome {
+        if self.over {
+            return GuessOutcome::GameOver;
+        }
+        if !self.range().contains(&guess) {
+            return GuessOutcome::OutOfRange { low: *self.range().start(), high: *self.range().end() };
+        }
+
+        self.attempts += 1;
+        // Some(0) is only refused by the command line, here it's one guess
+        let attempts_left = self.max_attempts().map(|max| max.saturating_sub(self.attempts));
+
+        let outcome = match guess.cmp(&self.secret) {
+            Ordering::Equal => GuessOutcome::Won { attempts: self.attempts },
+            _ if attempts_left == Some(0) => GuessOutcome::Lost { secret: self.secret },
+            Ordering::Less => GuessOutcome::TooSmall { attempts_left },
+            Ordering::Greater => GuessOutcome::TooBig { attempts_left },
+        };
+
+        self.over = matches!(outcome, GuessOutcome::Won { .. } | GuessOutcome::Lost { .. });
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn settings(range: RangeInclusive<u32>, max_attempts: Option<u32>) -> Settings {
+        Settings { range, max_attempts, debug: false }
+    }
+
+    #[test]
+    fn same_seed_same_secret() {
+        let settings = settings(1..=100, None);
+
+        let first = GuessingGame::new(&settings, &mut StdRng::seed_from_u64(7));
+        let second = GuessingGame::new(&settings, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(first.secret(), second.secret());
+        assert!(settings.range.contains(&first.secret()));
+    }
+
+    #[test]
+    fn feedback_until_won() {
+        let mut game = GuessingGame::with_secret(&settings(1..=100, None), 42);
+
+        assert_eq!(GuessOutcome::TooSmall { attempts_left: None }, game.guess(10));
+        assert_eq!(GuessOutcome::TooBig { attempts_left: None }, game.guess(90));
+        assert_eq!(GuessOutcome::Won { attempts: 3 }, game.guess(42));
+        assert_eq!(GuessOutcome::GameOver, game.guess(42));
+        assert_eq!(3, game.attempts());
+    }
+
+    #[test]
+    fn out_of_range_is_free() {
+        let mut game = GuessingGame::with_secret(&settings(1..=10, Some(2)), 5);
+
+        assert_eq!(GuessOutcome::OutOfRange { low: 1, high: 10 }, game.guess(11));
+        assert_eq!(GuessOutcome::OutOfRange { low: 1, high: 10 }, game.guess(0));
+        assert_eq!(0, game.attempts());
+    }
+
+    #[test]
+    fn running_out_of_attempts_loses() {
+        let mut game = GuessingGame::with_secret(&settings(1..=10, Some(2)), 5);
+
+        assert_eq!(GuessOutcome::TooBig { attempts_left: Some(1) }, game.guess(8));
+        assert_eq!(GuessOutcome::Lost { secret: 5 }, game.guess(3));
+        assert!(game.is_over());
+        assert_eq!(GuessOutcome::GameOver, game.guess(5));
+    }
+
+    #[test]
+    fn the_last_attempt_can_still_win() {
+        let mut game = GuessingGame::with_secret(&settings(1..=10, Some(1)), 5);
+
+        assert_eq!(GuessOutcome::Won { attempts: 1 }, game.guess(5));
+    }
+
+    #[test]
+    fn zero_attempts_is_one_guess() {
+        let mut game = GuessingGame::with_secret(&settings(1..=10, Some(0)), 5);
+        assert_eq!(GuessOutcome::Lost { secret: 5 }, game.guess(3));
+        assert_eq!(GuessOutcome::GameOver, game.guess(5));
+
+        let mut game = GuessingGame::with_secret(&settings(1..=10, Some(0)), 5);
+        assert_eq!(GuessOutcome::Won { attempts: 1 }, game.guess(5));
+    }
+}
@@ -1,41 +1,529 @@
-use rand::Rng;
-use std::{cmp::Ordering, io};
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    ops::RangeInclusive,
+    path::Path,
+    time::Instant,
+};
+
+use super::{
+    engine::{GuessOutcome, GuessingGame, Settings},
+    scores::{self, Score},
+    multiplayer,
+    strategy::{self, STRATEGIES},
+};
+
+// The presets give just enough attempts to always win by halving the range:
+// 10 numbers need 4 guesses, 100 need 7 and 1000 need 10.
+pub fn preset(name: &str) -> Option<(RangeInclusive<u32>, u32)> {
+    match name {
+        "easy" => Some((1..=10, 4)),
+        "normal" => Some((1..=100, 7)),
+        "hard" => Some((1..=1000, 10)),
+        _ => None,
+    }
+}
+
+// "1-500" -> 1..=500
+pub fn parse_range(text: &str) -> Option<RangeInclusive<u32>> {
+    let (low, high) = text.split_once('-')?;
+    let low: u32 = low.trim().parse().ok()?;
+    let high: u32 = high.trim().parse().ok()?;
+
+    if low > high {
+        return None;
+    }
+    Some(low..=high)
+}
+
+// What the command line asked for. Without a difficulty or a range, `range`
+// is None and the player picks one at start-up.
+#[derive(Debug, Default, PartialEq)]
+pub struct Flags {
+    pub range: Option<RangeInclusive<u32>>,
+    pub max_attempts: Option<u32>,
+    pub debug: bool,
+    // the name the game is recorded under
+    pub name: Option<String>,
+    // show the score table instead of playing
+    pub scores: bool,
+    // the strategy that plays instead of the player
+    pub autoplay: Option<String>,
+}
+
+// Reads --difficulty NAME, --range LOW-HIGH, --attempts N, --debug,
+// --name NAME, --scores and --autoplay STRATEGY.
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Flags, String> {
+    let mut range = None;
+    let mut preset_attempts = None;
+    let mut attempts = None;
+    let mut debug = false;
+    let mut name = None;
+    let mut scores = false;
+    let mut autoplay = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--difficulty" => {
+                let name = args.next().unwrap_or_default();
+                let (preset_range, preset_max) = preset(&name).ok_or(
+                    format!("Unknown difficulty '{name}', pick easy, normal or hard")
+                )?;
+                range = Some(preset_range);
+                preset_attempts = Some(preset_max);
+            }
+            "--range" => {
+                let text = args.next().unwrap_or_default();
+                range = Some(
+                    parse_range(&text).ok_or(format!("'{text}' isn't a range like 1-500"))?
+                );
+                preset_attempts = None;
+            }
+            "--attempts" => {
+                let text = args.next().unwrap_or_default();
+                match text.parse() {
+                    Ok(n) if n > 0 => {
+                        attempts = Some(n);
+                    }
+                    _ => {
+                        return Err(format!("'{text}' isn't a number of attempts"));
+                    }
+                }
+            }
+            "--debug" => {
+                debug = true;
+            }
+            "--name" => {
+                match args.next() {
+                    Some(text) if !text.trim().is_empty() => {
+                        name = Some(text.trim().to_string());
+                    }
+                    _ => {
+                        return Err(String::from("--name needs a name"));
+                    }
+                }
+            }
+            "--scores" => {
+                scores = true;
+            }
+            "--autoplay" => {
+                let name = args.next().unwrap_or_default();
+                if !STRATEGIES.contains(&name.as_str()) {
+                    return Err(format!("Unknown strategy '{name}', pick {}", STRATEGIES.join(", ")));
+                }
+                autoplay = Some(name);
+            }
+            _ => {
+                return Err(format!("Unknown option '{arg}'"));
+            }
+        }
+    }
+
+    // --attempts wins over the preset, wherever it is
+    Ok(Flags { range, max_attempts: attempts.or(preset_attempts), debug, name, scores, autoplay })
+}
+
+// Asks for a difficulty until it gets one: a preset name, or a custom range.
+// None when the input ends first.
+fn choose_range(
+    input: &mut impl BufRead,
+    output: &mut impl Write
+) -> io::Result<Option<(RangeInclusive<u32>, Option<u32>)>> {
+    loop {
+        writeln!(output, "Choose a difficulty: easy (1-10), normal (1-100), hard (1-1000), or a range like 1-500.")?;
+
+        let mut choice = String::new();
+        if input.read_line(&mut choice)? == 0 {
+            return Ok(None);
+        }
+        let choice = choice.trim();
+
+        if let Some((range, attempts)) = preset(choice) {
+            return Ok(Some((range, Some(attempts))));
+        }
+        if let Some(range) = parse_range(choice) {
+            return Ok(Some((range, None)));
+        }
+        writeln!(output, "'{choice}' isn't a difficulty.")?;
+    }
+}
 
 pub fn guess() {
+    // `bench` plays lots of games without anyone watching, `serve` and
+    // `join` play one with other people
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("bench") => {
+            args.next();
+            match strategy::run_bench(args) {
+                Ok(report) => print!("{report}"),
+                Err(err) => {
+                    println!("{err}");
+                    println!("Usage: bench [--games N] [--seed N] [--range LOW-HIGH]");
+                    std::process::exit(1)
+                }
+            }
+            return;
+        }
+        Some(command @ ("serve" | "join")) => {
+            let serving = command == "serve";
+            args.next();
+            let flags = multiplayer::parse_net_args(args).unwrap_or_else(|err| {
+                println!("{err}");
+                println!("Usage: serve [--port N] [--players N] [--difficulty easy|normal|hard] [--range LOW-HIGH]");
+                println!("       join [--port N] [--name NAME]");
+                std::process::exit(1)
+            });
+            if serving {
+                serve(flags);
+            } else {
+                join(flags);
+            }
+            return;
+        }
+        _ => {}
+    }
+
     println!("Guess the number!");
 
-    // Generates a random number between 1 and 100 (inclusive)
-    let secret_number = rand::thread_rng().gen_range(1..=100);
+    let flags = parse_args(args).unwrap_or_else(|err| {
+        println!("{err}");
+        println!(
+            "Usage: [--difficulty easy|normal|hard] [--range LOW-HIGH] [--attempts N] [--debug] [--name NAME] [--scores] [--autoplay binary|random|linear]"
+        );
+        std::process::exit(1)
+    });
+
+    let score_file = scores::locate();
+
+    if flags.scores {
+        show_scores(score_file.as_deref());
+        return;
+    }
+
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout();
+
+    let settings = match flags.range {
+        Some(range) => Settings { range, max_attempts: flags.max_attempts, debug: flags.debug },
+        None => {
+            let Some((range, preset_attempts)) = choose_range(&mut input, &mut output).expect(
+                "Failed to read line"
+            ) else {
+                return;
+            };
+            Settings { range, max_attempts: flags.max_attempts.or(preset_attempts), debug: flags.debug }
+        }
+    };
+
+    // Generates the secret number with the thread's random generator
+    let mut game = GuessingGame::new(&settings, &mut rand::thread_rng());
+
+    // the computer's games don't go on the score table
+    if let Some(name) = &flags.autoplay {
+        let mut player = strategy::by_name(name, rand::random()).expect("checked by parse_args");
+        strategy::autoplay(&mut game, player.as_mut(), &mut output).expect("Failed to write");
+        return;
+    }
+
+    let started = Instant::now();
+    let outcome = play(&mut game, &mut input, &mut output).expect("Failed to read line");
+
+    // a game that wasn't finished isn't recorded
+    let won = match outcome {
+        Some(GuessOutcome::Won { .. }) => true,
+        Some(GuessOutcome::Lost { .. }) => false,
+        _ => {
+            return;
+        }
+    };
+    let Some(score_file) = score_file else {
+        return;
+    };
+
+    let score = Score {
+        name: flags.name
+            .or_else(|| env::var("USER").ok())
+            .unwrap_or_else(|| String::from("player")),
+        range: settings.range,
+        attempts: game.attempts(),
+        won,
+        duration: started.elapsed(),
+        date: scores::now(),
+    };
+    // the game is over either way, losing the score is no reason to crash
+    if let Err(e) = scores::record(&score_file, &score) {
+        eprintln!("Couldn't save the score to {}: {e}", score_file.display());
+    }
+}
+
+fn serve(flags: multiplayer::NetFlags) {
+    let listener = TcpListener::bind(("127.0.0.1", flags.port)).unwrap_or_else(|e| {
+        println!("Couldn't listen on port {}: {e}", flags.port);
+        std::process::exit(1)
+    });
+    println!("Waiting for {} players on 127.0.0.1:{}...", flags.players, flags.port);
+
+    // no attempt limit, someone always gets there in the end
+    let settings = Settings { range: flags.range, max_attempts: None, debug: false };
+    let game = GuessingGame::new(&settings, &mut rand::thread_rng());
+
+    match multiplayer::serve(listener, game, flags.players) {
+        Ok(Some(winner)) => println!("{winner} won."),
+        Ok(None) => println!("Everyone left."),
+        Err(e) => println!("The game stopped: {e}"),
+    }
+}
 
-    println!("The secret number is: {secret_number}");
+fn join(flags: multiplayer::NetFlags) {
+    let name = flags.name
+        .or_else(|| env::var("USER").ok())
+        .unwrap_or_else(|| String::from("player"));
+
+    let joined = multiplayer::join(("127.0.0.1", flags.port), &name, BufReader::new(io::stdin()), &mut io::stdout());
+    if let Err(e) = joined {
+        println!("Couldn't play on port {}: {e}", flags.port);
+    }
+}
+
+fn show_scores(score_file: Option<&Path>) {
+    let Some(score_file) = score_file else {
+        println!("No games played yet.");
+        return;
+    };
+
+    match scores::load(score_file) {
+        Ok((scores, skipped)) => {
+            if skipped > 0 {
+                eprintln!("Skipped {skipped} damaged lines in {}", score_file.display());
+            }
+            print!("{}", scores::summary(&scores));
+        }
+        Err(e) => eprintln!("Couldn't read the scores from {}: {e}", score_file.display()),
+    }
+}
+
+// The terminal side of the game: reads guesses from `input`, hands them to
+// `game` and writes what happened to `output`. Returns the final outcome, or
+// None when the input ended before the game did.
+pub fn play(
+    game: &mut GuessingGame,
+    input: &mut impl BufRead,
+    output: &mut impl Write
+) -> io::Result<Option<GuessOutcome>> {
+    let (low, high) = (*game.range().start(), *game.range().end());
+
+    // only for testing the game itself, it spoils the fun otherwise
+    if game.settings().debug {
+        writeln!(output, "The secret number is: {}", game.secret())?;
+    }
+
+    match game.max_attempts() {
+        Some(max) => writeln!(output, "It's between {low} and {high}, and you have {max} attempts.")?,
+        None => writeln!(output, "It's between {low} and {high}.")?,
+    }
 
     loop {
-        println!("Please input your guess.");
+        writeln!(output, "Please input your guess.")?;
 
         let mut guess = String::new();
+        if input.read_line(&mut guess)? == 0 {
+            return Ok(None);
+        }
+        let guess = guess.trim();
 
-        io::stdin()
-            .read_line(&mut guess)
-            .expect("Failed to read line");
-
-        println!("You guessed: {guess}");
+        writeln!(output, "You guessed: {guess}")?;
 
         // Read user input as a string, trim whitespace,
         // and convert it to a u32 number
-        let guess: u32 = match guess.trim().parse() {
+        let guess: u32 = match guess.parse() {
             Ok(num) => num,
             Err(_) => {
+                writeln!(output, "'{guess}' isn't a number, please type a whole number like {low}.")?;
                 continue;
             }
         };
 
-        match guess.cmp(&secret_number) {
-            Ordering::Less => println!("Too small!"),
-            Ordering::Greater => println!("Too big!"),
-            Ordering::Equal => {
-                println!("You win!");
-                break;
+        let outcome = game.guess(guess);
+        match outcome {
+            GuessOutcome::TooSmall { .. } => writeln!(output, "Too small!")?,
+            GuessOutcome::TooBig { .. } => writeln!(output, "Too big!")?,
+            GuessOutcome::OutOfRange { low, high } => {
+                writeln!(output, "{guess} is out of range, the number is between {low} and {high}.")?
+            }
+            GuessOutcome::Won { attempts } => {
+                writeln!(output, "You win! It took you {attempts} attempts.")?;
+                return Ok(Some(outcome));
+            }
+            GuessOutcome::Lost { secret } => {
+                writeln!(output, "You lose, you're out of attempts! The number was {secret}.")?;
+                return Ok(Some(outcome));
+            }
+            GuessOutcome::GameOver => {
+                return Ok(None);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        list.iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn difficulty_sets_range_and_attempts() {
+        let flags = parse_args(args(&["--difficulty", "hard"])).unwrap();
+        assert_eq!(Flags { range: Some(1..=1000), max_attempts: Some(10), ..Flags::default() }, flags);
+
+        // --attempts wins over the preset, before or after it
+        let flags = parse_args(args(&["--attempts", "2", "--difficulty", "easy", "--debug"])).unwrap();
+        assert_eq!(Flags { range: Some(1..=10), max_attempts: Some(2), debug: true, ..Flags::default() }, flags);
+
+        // the last difficulty counts
+        let flags = parse_args(args(&["--difficulty", "easy", "--difficulty", "hard"])).unwrap();
+        assert_eq!(Some(10), flags.max_attempts);
+    }
+
+    #[test]
+    fn custom_ranges() {
+        let flags = parse_args(args(&["--difficulty", "easy", "--range", "5-50"])).unwrap();
+        assert_eq!(Flags { range: Some(5..=50), ..Flags::default() }, flags);
+        assert_eq!(Some(7..=7), parse_range("7 - 7"));
+        assert_eq!(None, parse_range("50-5"));
+        assert_eq!(None, parse_range("lots"));
+    }
+
+    #[test]
+    fn nothing_given_means_ask() {
+        assert_eq!(Ok(Flags::default()), parse_args(args(&[])));
+    }
+
+    #[test]
+    fn name_and_scores() {
+        let flags = parse_args(args(&["--name", " sam ", "--scores"])).unwrap();
+
+        assert_eq!(Flags { name: Some(String::from("sam")), scores: true, ..Flags::default() }, flags);
+        assert!(parse_args(args(&["--name"])).is_err());
+    }
+
+    #[test]
+    fn autoplay_takes_a_known_strategy() {
+        let flags = parse_args(args(&["--autoplay", "binary"])).unwrap();
+
+        assert_eq!(Some(String::from("binary")), flags.autoplay);
+        assert!(parse_args(args(&["--autoplay", "psychic"])).is_err());
+    }
+
+    #[test]
+    fn bad_flags_are_refused() {
+        assert!(parse_args(args(&["--difficulty", "nightmare"])).is_err());
+        assert!(parse_args(args(&["--attempts", "0"])).is_err());
+        assert!(parse_args(args(&["--cheat"])).is_err());
+    }
+
+    // Plays a whole game from a script of input lines and returns everything
+    // it printed.
+    fn script(game: &mut GuessingGame, lines: &[&str]) -> (Option<GuessOutcome>, String) {
+        let input = lines.join("\n");
+        let mut output = Vec::new();
+
+        let outcome = play(game, &mut input.as_bytes(), &mut output).unwrap();
+        (outcome, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn scripted_win_with_a_fixed_seed() {
+        let settings = Settings { range: 1..=100, max_attempts: Some(7), debug: false };
+        // the same seed always picks the same secret
+        let mut game = GuessingGame::new(&settings, &mut StdRng::seed_from_u64(2024));
+        assert_eq!(54, game.secret());
+
+        let (outcome, printed) = script(&mut game, &["fifty", "500", "50", " 75 ", "62", "56", "54"]);
+
+        assert_eq!(
+            "\
+It's between 1 and 100, and you have 7 attempts.
+Please input your guess.
+You guessed: fifty
+'fifty' isn't a number, please type a whole number like 1.
+Please input your guess.
+You guessed: 500
+500 is out of range, the number is between 1 and 100.
+Please input your guess.
+You guessed: 50
+Too small!
+Please input your guess.
+You guessed: 75
+Too big!
+Please input your guess.
+You guessed: 62
+Too big!
+Please input your guess.
+You guessed: 56
+Too big!
+Please input your guess.
+You guessed: 54
+You win! It took you 5 attempts.
+",
+            printed
+        );
+        // neither the typo nor the out of range guess used up an attempt
+        assert_eq!(Some(GuessOutcome::Won { attempts: 5 }), outcome);
+        assert_eq!(5, game.attempts());
+    }
+
+    #[test]
+    fn scripted_loss() {
+        let settings = Settings { range: 1..=10, max_attempts: Some(2), debug: false };
+        let mut game = GuessingGame::with_secret(&settings, 7);
+
+        let (outcome, printed) = script(&mut game, &["1", "2"]);
+
+        assert_eq!(
+            "\
+It's between 1 and 10, and you have 2 attempts.
+Please input your guess.
+You guessed: 1
+Too small!
+Please input your guess.
+You guessed: 2
+You lose, you're out of attempts! The number was 7.
+",
+            printed
+        );
+        assert_eq!(Some(GuessOutcome::Lost { secret: 7 }), outcome);
+    }
+
+    #[test]
+    fn debug_shows_the_secret_and_input_can_end_early() {
+        let settings = Settings { range: 1..=10, max_attempts: None, debug: true };
+        let mut game = GuessingGame::with_secret(&settings, 3);
+        let mut output = Vec::new();
+
+        let outcome = play(&mut game, &mut "5\n".as_bytes(), &mut output).unwrap();
+
+        let printed = String::from_utf8(output).unwrap();
+        assert!(printed.starts_with("The secret number is: 3\n"));
+        assert!(printed.ends_with("Too big!\nPlease input your guess.\n"));
+        assert_eq!(None, outcome);
+    }
+
+    #[test]
+    fn choose_range_asks_again() {
+        let mut output = Vec::new();
+
+        let chosen = choose_range(&mut "impossible\n3-30\n".as_bytes(), &mut output).unwrap();
+
+        assert_eq!(Some((3..=30, None)), chosen);
+        assert!(String::from_utf8(output).unwrap().contains("'impossible' isn't a difficulty."));
+    }
+}
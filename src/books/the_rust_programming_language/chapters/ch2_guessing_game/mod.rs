@@ -1 +1,5 @@
+pub mod engine;
 pub mod guessing_game;
+pub mod multiplayer;
+pub mod scores;
+pub mod strategy;
@@ -0,0 +1,607 @@
+// Guessing together over the network (serve, join)
+//
+// `serve` waits for the players on a TCP port, then they take turns guessing
+// the same secret. Every guess goes to everyone with how it compared to the
+// secret, and the first to get `Ordering::Equal` wins.
+//
+// The server is threads and a channel, like in ch16: one thread accepts the
+// players, one thread per player reads their lines, and all of them send what
+// happened down a single channel. The thread running the game is the only one
+// that touches it, so it needs no lock, and it writes to every player's socket.
+//
+// Everything on the wire is a line of text:
+//
+//   player -> server    the player's name, then one guess per line
+//   server -> player    NAME <name>              (the name they play under)
+//   server -> players   START <low> <high>
+//                       TURN <name>
+//                       GUESS <name> <number> <Less|Greater|Equal>
+//                       WINNER <name> <attempts>
+//                       LEFT <name>
+//                       ERROR <message>          (to one player only)
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    ops::RangeInclusive,
+    sync::mpsc,
+    thread,
+};
+
+use super::{
+    engine::{GuessOutcome, GuessingGame},
+    guessing_game::{parse_range, preset},
+};
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+// What the player threads tell the game.
+enum Event {
+    Joined { id: usize, name: String, stream: TcpStream },
+    Line { id: usize, text: String },
+    Left { id: usize },
+}
+
+struct Player {
+    id: usize,
+    name: String,
+    stream: TcpStream,
+}
+
+// Reads one player's lines until they hang up.
+fn read_player(id: usize, stream: TcpStream, tx: mpsc::Sender<Event>) -> io::Result<()> {
+    let writer = stream.try_clone()?;
+    let mut lines = BufReader::new(stream).lines();
+
+    let Some(name) = lines.next().transpose()? else {
+        return Ok(());
+    };
+    // names are one word, so they can go in the middle of a line
+    let name: String = name.split_whitespace().collect::<Vec<_>>().join("_");
+    let name = if name.is_empty() { format!("player{id}") } else { name };
+
+    if tx.send(Event::Joined { id, name, stream: writer }).is_err() {
+        return Ok(());
+    }
+    for line in lines {
+        let Ok(text) = line else {
+            break;
+        };
+        if tx.send(Event::Line { id, text }).is_err() {
+            return Ok(());
+        }
+    }
+    let _ = tx.send(Event::Left { id });
+    Ok(())
+}
+
+// `name`, or if someone already has it, `name` with the first number from
+// `id` up that nobody has either ("sam1" may have joined under that name).
+fn unique_name(players: &[Player], name: String, id: usize) -> String {
+    let taken = |candidate: &str| players.iter().any(|p| p.name == candidate);
+    if !taken(&name) {
+        return name;
+    }
+    (id..)
+        .map(|n| format!("{name}{n}"))
+        .find(|candidate| !taken(candidate))
+        .expect("there are fewer players than numbers")
+}
+
+// The game's side of the sockets. A write that fails means the player is
+// gone, which their reading thread will tell the game about.
+fn send(player: &mut Player, message: &str) {
+    let _ = writeln!(player.stream, "{message}");
+}
+
+fn broadcast(players: &mut [Player], message: &str) {
+    for player in players {
+        send(player, message);
+    }
+}
+
+// Hosts one round on `listener` for `wanted` players and returns the winner,
+// or None when everyone left first.
+pub fn serve(listener: TcpListener, mut game: GuessingGame, wanted: usize) -> io::Result<Option<String>> {
+    let (tx, rx) = mpsc::channel();
+
+    // Players who leave before the start free their place, so this keeps
+    // accepting; whoever comes too late is turned away by the game. The
+    // thread ends with the program, blocked in `accept`.
+    thread::spawn(move || {
+        for (id, stream) in listener.incoming().enumerate() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let tx = tx.clone();
+            thread::spawn(move || read_player(id, stream, tx));
+        }
+    });
+
+    let (low, high) = (*game.range().start(), *game.range().end());
+    let mut players: Vec<Player> = Vec::new();
+    let mut started = false;
+    // index into `players` of whoever's turn it is
+    let mut turn = 0;
+
+    for event in rx {
+        match event {
+            Event::Joined { id, name, mut stream } => {
+                if started {
+                    let _ = writeln!(stream, "ERROR the game has already started");
+                    // their reading thread still has the socket open, so
+                    // hang up for it: the late player sees the end of the
+                    // game instead of waiting for it
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                }
+                // two players called the same are told apart by a number,
+                // and each is told which name is theirs
+                let name = unique_name(&players, name, id);
+                let mut player = Player { id, name, stream };
+                let message = format!("NAME {}", player.name);
+                send(&mut player, &message);
+                players.push(player);
+
+                if players.len() == wanted {
+                    started = true;
+                    broadcast(&mut players, &format!("START {low} {high}"));
+                    let message = format!("TURN {}", players[turn].name);
+                    broadcast(&mut players, &message);
+                }
+            }
+            Event::Line { id, text } => {
+                let Some(at) = players.iter().position(|p| p.id == id) else {
+                    continue;
+                };
+
+                if !started {
+                    let waiting = wanted - players.len();
+                    send(&mut players[at], &format!("ERROR waiting for {waiting} more players"));
+                    continue;
+                }
+                if at != turn {
+                    let message = format!("ERROR it's {}'s turn", players[turn].name);
+                    send(&mut players[at], &message);
+                    continue;
+                }
+
+                let text = text.trim();
+                let Ok(guess) = text.parse::<u32>() else {
+                    send(&mut players[at], &format!("ERROR '{text}' isn't a number"));
+                    continue;
+                };
+
+                let name = players[at].name.clone();
+                let ordering = match game.guess(guess) {
+                    GuessOutcome::TooSmall { .. } => "Less",
+                    GuessOutcome::TooBig { .. } => "Greater",
+                    GuessOutcome::Won { attempts } => {
+                        broadcast(&mut players, &format!("GUESS {name} {guess} Equal"));
+                        broadcast(&mut players, &format!("WINNER {name} {attempts}"));
+                        return Ok(Some(name));
+                    }
+                    GuessOutcome::OutOfRange { low, high } => {
+                        send(&mut players[at], &format!("ERROR {guess} isn't between {low} and {high}"));
+                        continue;
+                    }
+                    // the shared game has no attempt limit, so it ends only
+                    // when someone wins
+                    GuessOutcome::Lost { .. } | GuessOutcome::GameOver => {
+                        return Ok(None);
+                    }
+                };
+
+                broadcast(&mut players, &format!("GUESS {name} {guess} {ordering}"));
+                turn = (turn + 1) % players.len();
+                let message = format!("TURN {}", players[turn].name);
+                broadcast(&mut players, &message);
+            }
+            Event::Left { id } => {
+                let Some(at) = players.iter().position(|p| p.id == id) else {
+                    continue;
+                };
+                let gone = players.remove(at);
+
+                if players.is_empty() {
+                    if started {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                broadcast(&mut players, &format!("LEFT {}", gone.name));
+
+                if started {
+                    if at < turn {
+                        // same player's turn, one place further up
+                        turn -= 1;
+                    } else if at == turn {
+                        // the player after them moved into their place
+                        turn %= players.len();
+                        let message = format!("TURN {}", players[turn].name);
+                        broadcast(&mut players, &message);
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+// A server line the way a player wants to read it. `me` is the player's own
+// name, None when the line isn't one the server sends.
+pub fn describe(line: &str, me: &str) -> Option<String> {
+    let words: Vec<&str> = line.split(' ').collect();
+
+    let text = match words[..] {
+        ["START", low, high] => format!("Everyone's here! The number is between {low} and {high}."),
+        ["TURN", name] if name == me => String::from("Your turn, please input your guess."),
+        ["TURN", name] => format!("{name}'s turn."),
+        ["GUESS", name, guess, ordering] => {
+            let feedback = match ordering {
+                "Less" => "Too small!",
+                "Greater" => "Too big!",
+                "Equal" => "That's it!",
+                _ => {
+                    return None;
+                }
+            };
+            format!("{name} guessed {guess}: {feedback}")
+        }
+        ["WINNER", name, _] if name == me => String::from("You win!"),
+        ["WINNER", name, attempts] => format!("{name} wins, after {attempts} guesses between you."),
+        ["LEFT", name] => format!("{name} left the game."),
+        ["ERROR", ..] => line["ERROR ".len()..].to_string(),
+        _ => {
+            return None;
+        }
+    };
+    Some(text)
+}
+
+// The player's side: `input` goes to the server, what comes back is written
+// to `output` until somebody wins. Reading `input` happens on its own thread
+// so the other players' guesses show up while this one is thinking.
+pub fn join(
+    address: impl ToSocketAddrs,
+    name: &str,
+    input: impl BufRead + Send + 'static,
+    output: &mut impl Write
+) -> io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    let mut to_server = stream.try_clone()?;
+    writeln!(to_server, "{name}")?;
+
+    thread::spawn(move || {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if writeln!(to_server, "{line}").is_err() {
+                break;
+            }
+        }
+    });
+
+    // what the server will call us, unless it has to tell two players apart:
+    // then it says so with a NAME line
+    let mut me: String = name.split_whitespace().collect::<Vec<_>>().join("_");
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if let Some(assigned) = line.strip_prefix("NAME ") {
+            if assigned != me {
+                writeln!(output, "{me} is taken, you're playing as {assigned}.")?;
+                me = assigned.to_string();
+            }
+            continue;
+        }
+        if let Some(text) = describe(&line, &me) {
+            writeln!(output, "{text}")?;
+        }
+        if line.starts_with("WINNER ") {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// What `serve` and `join` were asked for on the command line.
+#[derive(Debug, PartialEq)]
+pub struct NetFlags {
+    pub port: u16,
+    pub players: usize,
+    pub range: RangeInclusive<u32>,
+    pub name: Option<String>,
+}
+
+// --port N, --players N, --difficulty NAME, --range LOW-HIGH and --name NAME.
+pub fn parse_net_args(mut args: impl Iterator<Item = String>) -> Result<NetFlags, String> {
+    let mut flags = NetFlags { port: DEFAULT_PORT, players: 2, range: 1..=100, name: None };
+
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_default();
+        match arg.as_str() {
+            "--port" => {
+                flags.port = value.parse().map_err(|_| format!("'{value}' isn't a port"))?;
+            }
+            "--players" => {
+                flags.players = value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or(format!("'{value}' isn't a number of players"))?;
+            }
+            "--difficulty" => {
+                flags.range = preset(&value)
+                    .map(|(range, _)| range)
+                    .ok_or(format!("Unknown difficulty '{value}', pick easy, normal or hard"))?;
+            }
+            "--range" => {
+                flags.range = parse_range(&value).ok_or(format!("'{value}' isn't a range like 1-500"))?;
+            }
+            "--name" => {
+                flags.name = Some(value);
+            }
+            _ => {
+                return Err(format!("Unknown option '{arg}'"));
+            }
+        }
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::engine::Settings;
+    use std::{ net::SocketAddr, thread::JoinHandle, time::Duration };
+
+    // A server on a free port of 127.0.0.1, with a known secret.
+    fn start(secret: u32, wanted: usize) -> (SocketAddr, JoinHandle<io::Result<Option<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let settings = Settings { range: 1..=100, max_attempts: None, debug: false };
+        let game = GuessingGame::with_secret(&settings, secret);
+
+        (address, thread::spawn(move || serve(listener, game, wanted)))
+    }
+
+    struct Client {
+        reader: io::Lines<BufReader<TcpStream>>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(address: SocketAddr, name: &str) -> Client {
+            let mut client = Client::open(address);
+            client.say(name);
+            client
+        }
+
+        // Connected, but the name is still to come.
+        fn open(address: SocketAddr) -> Client {
+            let stream = TcpStream::connect(address).unwrap();
+            // a test that goes wrong fails instead of hanging
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let writer = stream.try_clone().unwrap();
+
+            Client { reader: BufReader::new(stream).lines(), writer }
+        }
+
+        fn say(&mut self, line: &str) {
+            writeln!(self.writer, "{line}").unwrap();
+        }
+
+        fn hear(&mut self) -> String {
+            self.reader.next().unwrap().unwrap()
+        }
+    }
+
+    // Connects the players one after the other. Each one says something
+    // before the next connects and waits for the answer, so the server has
+    // them in this order. The last one's answer is the start of the game.
+    fn connect_in_order(address: SocketAddr, names: &[&str]) -> Vec<Client> {
+        let mut clients = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            let mut client = Client::connect(address, name);
+            assert_eq!(format!("NAME {name}"), client.hear());
+            if i + 1 < names.len() {
+                client.say("too early");
+                assert_eq!(format!("ERROR waiting for {} more players", names.len() - i - 1), client.hear());
+            }
+            clients.push(client);
+        }
+        for client in &mut clients {
+            assert_eq!("START 1 100", client.hear());
+            assert_eq!(format!("TURN {}", names[0]), client.hear());
+        }
+        clients
+    }
+
+    #[test]
+    fn players_take_turns_and_the_first_equal_wins() {
+        let (address, server) = start(42, 2);
+        let [mut sam, mut alex] = connect_in_order(address, &["sam", "alex"]).try_into().ok().unwrap();
+
+        alex.say("10");
+        assert_eq!("ERROR it's sam's turn", alex.hear());
+        sam.say("50");
+        for client in [&mut sam, &mut alex] {
+            assert_eq!("GUESS sam 50 Greater", client.hear());
+            assert_eq!("TURN alex", client.hear());
+        }
+
+        alex.say("five");
+        assert_eq!("ERROR 'five' isn't a number", alex.hear());
+        alex.say("500");
+        assert_eq!("ERROR 500 isn't between 1 and 100", alex.hear());
+        alex.say("25");
+        for client in [&mut sam, &mut alex] {
+            assert_eq!("GUESS alex 25 Less", client.hear());
+            assert_eq!("TURN sam", client.hear());
+        }
+
+        sam.say("42");
+        for client in [&mut sam, &mut alex] {
+            assert_eq!("GUESS sam 42 Equal", client.hear());
+            assert_eq!("WINNER sam 3", client.hear());
+        }
+        assert_eq!(Some(String::from("sam")), server.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn late_players_are_turned_away() {
+        let (address, _server) = start(42, 1);
+        let _solo = connect_in_order(address, &["solo"]);
+
+        let mut late = Client::connect(address, "late");
+
+        assert_eq!("ERROR the game has already started", late.hear());
+        assert!(late.reader.next().is_none());
+
+        // and `join` doesn't wait for a game it isn't in
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            let joined = join(address, "later", io::empty(), &mut output);
+            let _ = tx.send(joined.map(|()| String::from_utf8(output).unwrap()));
+        });
+        let printed = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert!(printed.contains("the game has already started"), "{printed}");
+    }
+
+    #[test]
+    fn leaving_passes_the_turn_on() {
+        let (address, server) = start(7, 3);
+        let mut clients = connect_in_order(address, &["a", "b", "c"]);
+
+        // "a" leaves on their turn, it goes to the next one
+        drop(clients.remove(0));
+        for client in &mut clients {
+            assert_eq!("LEFT a", client.hear());
+            assert_eq!("TURN b", client.hear());
+        }
+
+        // "c" leaves while it isn't their turn, it stays with "b"
+        drop(clients.remove(1));
+        assert_eq!("LEFT c", clients[0].hear());
+        clients[0].say("1");
+        assert_eq!("GUESS b 1 Less", clients[0].hear());
+        assert_eq!("TURN b", clients[0].hear());
+
+        drop(clients);
+        assert_eq!(None, server.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn server_lines_read_like_the_game() {
+        assert_eq!(Some(String::from("Your turn, please input your guess.")), describe("TURN sam", "sam"));
+        assert_eq!(Some(String::from("alex's turn.")), describe("TURN alex", "sam"));
+        assert_eq!(Some(String::from("alex guessed 50: Too big!")), describe("GUESS alex 50 Greater", "sam"));
+        assert_eq!(Some(String::from("You win!")), describe("WINNER sam 4", "sam"));
+        assert_eq!(Some(String::from("it's sam's turn")), describe("ERROR it's sam's turn", "alex"));
+        assert_eq!(None, describe("GUESS alex 50 Sideways", "sam"));
+        assert_eq!(None, describe("HELLO", "sam"));
+    }
+
+    #[test]
+    fn join_plays_a_round() {
+        let (address, server) = start(3, 1);
+        let mut output = Vec::new();
+
+        join(address, "solo", io::Cursor::new("1\n3\n"), &mut output).unwrap();
+
+        assert_eq!(
+            "\
+Everyone's here! The number is between 1 and 100.
+Your turn, please input your guess.
+solo guessed 1: Too small!
+Your turn, please input your guess.
+solo guessed 3: That's it!
+You win!
+",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(Some(String::from("solo")), server.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn a_taken_name_gets_a_number() {
+        let (address, server) = start(3, 2);
+        let mut sam = Client::connect(address, "sam");
+        assert_eq!("NAME sam", sam.hear());
+        sam.say("too early");
+        assert_eq!("ERROR waiting for 1 more players", sam.hear());
+
+        // the second sam types on a socket, so their guess is sent only when
+        // the test says so
+        let keys = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut keyboard = TcpStream::connect(keys.local_addr().unwrap()).unwrap();
+        let typed = BufReader::new(keys.accept().unwrap().0);
+        let player = thread::spawn(move || {
+            let mut output = Vec::new();
+            join(address, "sam", typed, &mut output).map(|()| String::from_utf8(output).unwrap())
+        });
+
+        assert_eq!("START 1 100", sam.hear());
+        assert_eq!("TURN sam", sam.hear());
+        sam.say("1");
+        assert_eq!("GUESS sam 1 Less", sam.hear());
+        assert_eq!("TURN sam1", sam.hear());
+        writeln!(keyboard, "3").unwrap();
+        assert_eq!("GUESS sam1 3 Equal", sam.hear());
+        assert_eq!("WINNER sam1 2", sam.hear());
+
+        assert_eq!(
+            "\
+sam is taken, you're playing as sam1.
+Everyone's here! The number is between 1 and 100.
+sam's turn.
+sam guessed 1: Too small!
+Your turn, please input your guess.
+sam1 guessed 3: That's it!
+You win!
+",
+            player.join().unwrap().unwrap()
+        );
+        assert_eq!(Some(String::from("sam1")), server.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn a_new_name_is_not_taken_either() {
+        let (address, server) = start(3, 3);
+        // connected in this order, so they get the numbers 0, 1 and 2
+        let [mut first, mut second, mut third] = [(); 3].map(|()| Client::open(address));
+
+        // the last to connect is the first with a name, and takes "sam1"
+        // before the second "sam" would be given it
+        third.say("sam1");
+        assert_eq!("NAME sam1", third.hear());
+        first.say("sam");
+        assert_eq!("NAME sam", first.hear());
+        second.say("sam");
+        assert_eq!("NAME sam2", second.hear());
+
+        for client in [&mut first, &mut second, &mut third] {
+            assert_eq!("START 1 100", client.hear());
+            assert_eq!("TURN sam1", client.hear());
+        }
+        third.say("3");
+        for client in [&mut first, &mut second, &mut third] {
+            assert_eq!("GUESS sam1 3 Equal", client.hear());
+            assert_eq!("WINNER sam1 1", client.hear());
+        }
+        assert_eq!(Some(String::from("sam1")), server.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn net_options() {
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>().into_iter();
+
+        let flags = parse_net_args(args(&["--port", "9000", "--players", "3", "--difficulty", "hard"])).unwrap();
+        assert_eq!(NetFlags { port: 9000, players: 3, range: 1..=1000, name: None }, flags);
+        assert!(parse_net_args(args(&["--players", "0"])).is_err());
+        assert!(parse_net_args(args(&["--port", "99999"])).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+// The score file: every finished game, one line each
+//
+//   date    name   range  attempts  result  duration (ms)
+//   1760745600\tsam\t1-100\t6\twon\t41200
+//
+// Tabs between the fields, so a name can have spaces in it. Lines that can't
+// be read back (a half-written line, someone editing the file by hand) are
+// skipped when the scores are shown and kept as they are when a game is
+// added, so nothing the player had is ever thrown away.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::temp_file;
+
+use super::guessing_game::preset;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub name: String,
+    pub range: RangeInclusive<u32>,
+    pub attempts: u32,
+    pub won: bool,
+    pub duration: Duration,
+    // seconds since 1970, in UTC
+    pub date: u64,
+}
+
+impl Score {
+    pub fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}-{}\t{}\t{}\t{}",
+            self.date,
+            // a tab or a newline in the name would break the line apart
+            self.name.replace(['\t', '\n', '\r'], " "),
+            self.range.start(),
+            self.range.end(),
+            self.attempts,
+            if self.won { "won" } else { "lost" },
+            self.duration.as_millis()
+        )
+    }
+
+    // None for a line that isn't a score.
+    pub fn from_line(line: &str) -> Option<Score> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [date, name, range, attempts, result, millis] = fields[..] else {
+            return None;
+        };
+
+        let (low, high) = range.split_once('-')?;
+        let (low, high): (u32, u32) = (low.parse().ok()?, high.parse().ok()?);
+        if low > high || name.is_empty() {
+            return None;
+        }
+
+        let won = match result {
+            "won" => true,
+            "lost" => false,
+            _ => {
+                return None;
+            }
+        };
+
+        Some(Score {
+            name: name.to_string(),
+            range: low..=high,
+            attempts: attempts.parse().ok()?,
+            won,
+            duration: Duration::from_millis(millis.parse().ok()?),
+            date: date.parse().ok()?,
+        })
+    }
+
+    pub fn difficulty(&self) -> String {
+        difficulty(&self.range)
+    }
+}
+
+// The preset's name when the range is one, "1-500" otherwise.
+pub fn difficulty(range: &RangeInclusive<u32>) -> String {
+    for name in ["easy", "normal", "hard"] {
+        if preset(name).is_some_and(|(preset_range, _)| preset_range == *range) {
+            return name.to_string();
+        }
+    }
+    format!("{}-{}", range.start(), range.end())
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+// Where the scores are kept: $GUESSING_GAME_SCORES, or a file in the home
+// directory.
+pub fn locate() -> Option<PathBuf> {
+    if let Ok(path) = env::var("GUESSING_GAME_SCORES") {
+        return Some(PathBuf::from(path));
+    }
+
+    let home = env::var("HOME").ok()?;
+    Some(Path::new(&home).join(".guessing_game_scores"))
+}
+
+// The file as it is, empty if there isn't one yet.
+fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// The file as text. Bytes that aren't UTF-8 only spoil the lines they are on.
+fn read_text(path: &Path) -> io::Result<String> {
+    Ok(String::from_utf8_lossy(&read_bytes(path)?).into_owned())
+}
+
+// Every score in the file, and how many lines had to be skipped.
+pub fn load(path: &Path) -> io::Result<(Vec<Score>, usize)> {
+    let text = read_text(path)?;
+    let mut scores = Vec::new();
+    let mut skipped = 0;
+
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        match Score::from_line(line) {
+            Some(score) => scores.push(score),
+            None => {
+                skipped += 1;
+            }
+        }
+    }
+    Ok((scores, skipped))
+}
+
+// Adds a game to the file. The new file is written next to the old one and
+// renamed over it, so a crash halfway leaves the old scores as they were
+// instead of half a file. The old lines are copied byte for byte, not as
+// text, so even the ones that aren't UTF-8 come out as they went in.
+//
+// Two games can end at the same time (two terminals, or `serve`). Reading,
+// adding and renaming happen while holding a lock on `path + ".lock"`, so
+// the second game reads the file with the first one's score already in it.
+// The lock can't be on the score file itself: the rename replaces it.
+pub fn record(path: &Path, score: &Score) -> io::Result<()> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock = OpenOptions::new().create(true).truncate(false).write(true).open(PathBuf::from(lock_path))?;
+    // let go of when `lock` is dropped, at the end
+    lock.lock()?;
+
+    let mut bytes = read_bytes(path)?;
+    if !bytes.is_empty() && !bytes.ends_with(b"\n") {
+        bytes.push(b'\n');
+    }
+    bytes.extend_from_slice(score.to_line().as_bytes());
+    bytes.push(b'\n');
+
+    let (temp, mut file) = temp_file::create_next_to(path)?;
+    let result = file
+        .write_all(&bytes)
+        .and_then(|()| file.sync_all())
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// "2025-10-18" for a date in seconds since 1970.
+pub fn format_date(date: u64) -> String {
+    // days to year/month/day, Howard Hinnant's `civil_from_days`
+    let days = (date / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+// The presets first, easiest first, then the custom ranges.
+fn difficulty_order(name: &str) -> (usize, String) {
+    let rank = ["easy", "normal", "hard"]
+        .iter()
+        .position(|preset| *preset == name)
+        .unwrap_or(3);
+    (rank, name.to_string())
+}
+
+// What `--scores` prints: the best game and the averages per difficulty,
+// then the win rate day by day.
+pub fn summary(scores: &[Score]) -> String {
+    if scores.is_empty() {
+        return String::from("No games played yet.\n");
+    }
+
+    let mut by_difficulty: BTreeMap<(usize, String), Vec<&Score>> = BTreeMap::new();
+    for score in scores {
+        by_difficulty.entry(difficulty_order(&score.difficulty())).or_default().push(score);
+    }
+
+    let mut text = String::from("Best results\n");
+    for ((_, name), games) in &by_difficulty {
+        let wins: Vec<&&Score> = games
+            .iter()
+            .filter(|score| score.won)
+            .collect();
+
+        text.push_str(&format!("  {name}: "));
+
+        // fewest attempts, then the fastest, then the first to do it
+        match wins.iter().min_by_key(|score| (score.attempts, score.duration, score.date)) {
+            Some(best) => text.push_str(&format!(
+                "{} in {} attempts ({:.1}s, {})",
+                best.name,
+                best.attempts,
+                best.duration.as_secs_f64(),
+                format_date(best.date)
+            )),
+            None => text.push_str("not won yet"),
+        }
+
+        let won = wins.len();
+        let played = games.len();
+        text.push_str(&format!(", {won} of {played} won"));
+        if won > 0 {
+            let average = (wins.iter().map(|score| score.attempts).sum::<u32>() as f64) / (won as f64);
+            text.push_str(&format!(", {average:.1} attempts on average"));
+        }
+        text.push('\n');
+    }
+
+    let mut by_day: BTreeMap<u64, (usize, usize)> = BTreeMap::new();
+    for score in scores {
+        let (won, played) = by_day.entry(score.date / 86_400).or_default();
+        *won += usize::from(score.won);
+        *played += 1;
+    }
+
+    text.push_str("Win rate\n");
+    for (day, (won, played)) in by_day {
+        text.push_str(&format!(
+            "  {}: {won} of {played} won ({}%)\n",
+            format_date(day * 86_400),
+            (won * 100) / played
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(name: &str, range: RangeInclusive<u32>, attempts: u32, won: bool, date: u64) -> Score {
+        Score { name: name.to_string(), range, attempts, won, duration: Duration::from_millis(1500), date }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("guessing-game-{name}-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn lines_read_back() {
+        let sam = score("sam the\tgreat", 1..=100, 6, true, 1_760_745_600);
+
+        let line = sam.to_line();
+
+        assert_eq!("1760745600\tsam the great\t1-100\t6\twon\t1500", line);
+        assert_eq!(Some(Score { name: String::from("sam the great"), ..sam }), Score::from_line(&line));
+        assert_eq!(None, Score::from_line("1760745600\tsam\t1-100\tsix\twon\t1500"));
+        assert_eq!(None, Score::from_line("1760745600\tsam\t1-100"));
+    }
+
+    #[test]
+    fn dates_are_days_in_utc() {
+        assert_eq!("1970-01-01", format_date(0));
+        assert_eq!("2000-02-29", format_date(951_782_400));
+        assert_eq!("2025-10-18", format_date(1_760_745_600 + 86_399));
+    }
+
+    #[test]
+    fn difficulties_are_named_after_presets() {
+        assert_eq!("normal", difficulty(&(1..=100)));
+        assert_eq!("1-500", difficulty(&(1..=500)));
+    }
+
+    #[test]
+    fn recording_keeps_what_was_there() {
+        let path = temp_file("record");
+        fs::write(&path, b"this is not a score\n\xff\xfe\n1760745600\tsam\t1-10\t3\twon\t900").unwrap();
+
+        record(&path, &score("alex", 1..=10, 4, false, 1_760_832_000)).unwrap();
+        let (scores, skipped) = load(&path).unwrap();
+
+        assert_eq!(2, skipped);
+        assert_eq!(vec!["sam", "alex"], scores.iter().map(|s| s.name.as_str()).collect::<Vec<_>>());
+        // the damaged lines are still there, invalid bytes and all
+        assert!(fs::read(&path).unwrap().starts_with(b"this is not a score\n\xff\xfe\n1760745600\tsam\t1-10\t3\twon\t900\n"));
+        assert_eq!(0, leftover_temp_files(&path));
+    }
+
+    #[test]
+    fn games_recorded_at_the_same_time_are_all_kept() {
+        let path = temp_file("race");
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for j in 0..5 {
+                        record(path, &score(&format!("p{i}-{j}"), 1..=10, 3, true, 1_760_745_600)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let (scores, skipped) = load(&path).unwrap();
+        assert_eq!((40, 0), (scores.len(), skipped));
+        assert_eq!(0, leftover_temp_files(&path));
+    }
+
+    // The temporary files `record` didn't rename or remove.
+    fn leftover_temp_files(path: &Path) -> usize {
+        let prefix = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+        fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with(&prefix))
+            .count()
+    }
+
+    #[test]
+    fn no_file_is_no_scores() {
+        assert_eq!((Vec::new(), 0), load(&temp_file("missing")).unwrap());
+        assert_eq!("No games played yet.\n", summary(&[]));
+    }
+
+    #[test]
+    fn summary_per_difficulty_and_day() {
+        let day = 1_760_745_600;
+        let scores = vec![
+            score("sam", 1..=100, 7, true, day),
+            score("alex", 1..=1000, 10, false, day),
+            score("alex", 1..=100, 5, true, day + 86_400),
+            score("sam", 1..=10, 3, true, day + 86_400),
+            score("sam", 1..=100, 7, false, day + 86_400),
+            score("kim", 1..=500, 9, true, day + 86_400),
+        ];
+
+        assert_eq!(
+            "\
+Best results
+  easy: sam in 3 attempts (1.5s, 2025-10-19), 1 of 1 won, 3.0 attempts on average
+  normal: alex in 5 attempts (1.5s, 2025-10-19), 2 of 3 won, 6.0 attempts on average
+  hard: not won yet, 0 of 1 won
+  1-500: kim in 9 attempts (1.5s, 2025-10-19), 1 of 1 won, 9.0 attempts on average
+Win rate
+  2025-10-18: 1 of 2 won (50%)
+  2025-10-19: 3 of 4 won (75%)
+",
+            summary(&scores)
+        );
+    }
+}
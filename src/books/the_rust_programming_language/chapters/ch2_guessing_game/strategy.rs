@@ -0,0 +1,417 @@
+// Letting the computer play (--autoplay, bench)
+//
+// A strategy only ever learns what a player would: whether its guess was too
+// small or too big. It plays the same `GuessingGame` that `guess()` does, so
+// it can't peek at the secret.
+//
+// Halving what's left every time finds any number out of N in at most
+// ceil(log2(N + 1)) guesses: 7 for 1-100, 10 for 1-1000. `bench` plays many
+// games with each strategy to show it.
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    ops::RangeInclusive,
+};
+
+use super::{
+    engine::{GuessOutcome, GuessingGame, Settings},
+    guessing_game::parse_range,
+};
+
+pub trait Strategy {
+    fn name(&self) -> &'static str;
+
+    // A new game is starting, the secret is somewhere in `range`.
+    fn start(&mut self, range: RangeInclusive<u32>);
+
+    fn next_guess(&mut self) -> u32;
+
+    // How `guess` compared to the secret: Less is "Too small!".
+    fn learn(&mut self, guess: u32, ordering: Ordering);
+}
+
+// Guesses the middle of what's left.
+#[derive(Debug, Default)]
+pub struct BinarySearch {
+    low: u32,
+    high: u32,
+}
+
+impl Strategy for BinarySearch {
+    fn name(&self) -> &'static str {
+        "binary"
+    }
+
+    fn start(&mut self, range: RangeInclusive<u32>) {
+        (self.low, self.high) = range.into_inner();
+    }
+
+    fn next_guess(&mut self) -> u32 {
+        // not (low + high) / 2, which overflows near u32::MAX
+        self.low + (self.high - self.low) / 2
+    }
+
+    fn learn(&mut self, guess: u32, ordering: Ordering) {
+        match ordering {
+            Ordering::Less => {
+                self.low = guess + 1;
+            }
+            Ordering::Greater => {
+                self.high = guess - 1;
+            }
+            Ordering::Equal => {}
+        }
+    }
+}
+
+// Guesses anything that's still possible, so it does listen to the hints but
+// only cuts off a random part of what's left.
+pub struct RandomGuess<R: Rng> {
+    rng: R,
+    low: u32,
+    high: u32,
+}
+
+impl<R: Rng> RandomGuess<R> {
+    pub fn new(rng: R) -> RandomGuess<R> {
+        RandomGuess { rng, low: 0, high: 0 }
+    }
+}
+
+impl<R: Rng> Strategy for RandomGuess<R> {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn start(&mut self, range: RangeInclusive<u32>) {
+        (self.low, self.high) = range.into_inner();
+    }
+
+    fn next_guess(&mut self) -> u32 {
+        self.rng.gen_range(self.low..=self.high)
+    }
+
+    fn learn(&mut self, guess: u32, ordering: Ordering) {
+        match ordering {
+            Ordering::Less => {
+                self.low = guess + 1;
+            }
+            Ordering::Greater => {
+                self.high = guess - 1;
+            }
+            Ordering::Equal => {}
+        }
+    }
+}
+
+// The bad one on purpose: counts up from the bottom and ignores the hints,
+// so it needs as many guesses as the secret is far from the start.
+#[derive(Debug, Default)]
+pub struct Linear {
+    next: u32,
+}
+
+impl Strategy for Linear {
+    fn name(&self) -> &'static str {
+        "linear"
+    }
+
+    fn start(&mut self, range: RangeInclusive<u32>) {
+        self.next = *range.start();
+    }
+
+    fn next_guess(&mut self) -> u32 {
+        self.next
+    }
+
+    fn learn(&mut self, _guess: u32, _ordering: Ordering) {
+        self.next += 1;
+    }
+}
+
+pub const STRATEGIES: [&str; 3] = ["binary", "random", "linear"];
+
+pub fn by_name(name: &str, seed: u64) -> Option<Box<dyn Strategy>> {
+    match name {
+        "binary" => Some(Box::new(BinarySearch::default())),
+        "random" => Some(Box::new(RandomGuess::new(StdRng::seed_from_u64(seed)))),
+        "linear" => Some(Box::new(Linear::default())),
+        _ => None,
+    }
+}
+
+// Plays `game` to the end, writing every guess and answer to `output` the
+// way the terminal game would.
+pub fn autoplay(
+    game: &mut GuessingGame,
+    strategy: &mut dyn Strategy,
+    output: &mut impl Write
+) -> io::Result<GuessOutcome> {
+    strategy.start(game.range().clone());
+
+    loop {
+        let guess = strategy.next_guess();
+        writeln!(output, "{} guessed: {guess}", strategy.name())?;
+
+        let outcome = game.guess(guess);
+        match outcome {
+            GuessOutcome::TooSmall { .. } => {
+                writeln!(output, "Too small!")?;
+                strategy.learn(guess, Ordering::Less);
+            }
+            GuessOutcome::TooBig { .. } => {
+                writeln!(output, "Too big!")?;
+                strategy.learn(guess, Ordering::Greater);
+            }
+            GuessOutcome::Won { attempts } => {
+                writeln!(output, "{} wins! It took {attempts} attempts.", strategy.name())?;
+                return Ok(outcome);
+            }
+            GuessOutcome::Lost { secret } => {
+                writeln!(output, "{} is out of attempts! The number was {secret}.", strategy.name())?;
+                return Ok(outcome);
+            }
+            // none of the strategies ever guess outside the range, but a
+            // strategy that did would loop forever
+            GuessOutcome::OutOfRange { .. } | GuessOutcome::GameOver => {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+// The guesses one strategy needed over many games.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: &'static str,
+    // how many games took each number of attempts
+    pub distribution: BTreeMap<u32, usize>,
+}
+
+impl BenchResult {
+    pub fn games(&self) -> usize {
+        self.distribution.values().sum()
+    }
+
+    pub fn mean(&self) -> f64 {
+        let total: u64 = self.distribution
+            .iter()
+            .map(|(attempts, games)| u64::from(*attempts) * (*games as u64))
+            .sum();
+        (total as f64) / (self.games() as f64)
+    }
+
+    pub fn max(&self) -> u32 {
+        self.distribution.keys().next_back().copied().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for BenchResult {
+    // A header line, then a bar per number of attempts. Long tails (linear
+    // needs up to N guesses) are put together in up to 10 rows.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: mean {:.2}, max {}", self.name, self.mean(), self.max())?;
+
+        let width = self.max().div_ceil(10).max(1);
+        let mut rows: BTreeMap<u32, usize> = BTreeMap::new();
+        for (attempts, games) in &self.distribution {
+            *rows.entry((attempts - 1) / width).or_default() += games;
+        }
+
+        let most = rows.values().copied().max().unwrap_or(1);
+        for (row, games) in rows {
+            let label = if width == 1 {
+                (row + 1).to_string()
+            } else {
+                format!("{}-{}", row * width + 1, (row + 1) * width)
+            };
+            // 40 columns for the most common row
+            let bar = "#".repeat((games * 40).div_ceil(most));
+            writeln!(f, "  {label:>9} {bar} {games}")?;
+        }
+        Ok(())
+    }
+}
+
+// The most guesses halving ever needs for a range: ceil(log2(N + 1)).
+pub fn binary_search_bound(range: &RangeInclusive<u32>) -> u32 {
+    let numbers = u64::from(range.end() - range.start()) + 1;
+    // the bits needed to write N down
+    u64::BITS - numbers.leading_zeros()
+}
+
+// Plays `games` games with each strategy. Game i has the same secret for all
+// of them, picked from `seed + i`, so they're compared on the same numbers.
+pub fn bench(names: &[&str], range: RangeInclusive<u32>, games: usize, seed: u64) -> Vec<BenchResult> {
+    // no attempt limit, or the slow strategies would just lose
+    let settings = Settings { range, max_attempts: None, debug: false };
+
+    names
+        .iter()
+        .filter_map(|name| by_name(name, seed))
+        .map(|mut strategy| {
+            let mut distribution = BTreeMap::new();
+
+            for i in 0..games {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+                let mut game = GuessingGame::new(&settings, &mut rng);
+
+                if let Ok(GuessOutcome::Won { attempts }) = autoplay(&mut game, strategy.as_mut(), &mut io::sink()) {
+                    *distribution.entry(attempts).or_default() += 1;
+                }
+            }
+            BenchResult { name: strategy.name(), distribution }
+        })
+        .collect()
+}
+
+// bench [--games N] [--seed N] [--range LOW-HIGH]
+pub fn run_bench(mut args: impl Iterator<Item = String>) -> Result<String, String> {
+    let mut range = 1..=100;
+    let mut games = 1000;
+    let mut seed = 0;
+
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_default();
+        match arg.as_str() {
+            "--games" => {
+                games = value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or(format!("'{value}' isn't a number of games"))?;
+            }
+            "--seed" => {
+                seed = value.parse().map_err(|_| format!("'{value}' isn't a seed"))?;
+            }
+            "--range" => {
+                range = parse_range(&value).ok_or(format!("'{value}' isn't a range like 1-500"))?;
+            }
+            _ => {
+                return Err(format!("Unknown bench option '{arg}'"));
+            }
+        }
+    }
+
+    let bound = binary_search_bound(&range);
+    let mut report = format!(
+        "{games} games on {}-{}, halving needs at most {bound} guesses\n",
+        range.start(),
+        range.end()
+    );
+    for result in bench(&STRATEGIES, range, games, seed) {
+        report.push_str(&result.to_string());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(range: RangeInclusive<u32>) -> Settings {
+        Settings { range, max_attempts: None, debug: false }
+    }
+
+    #[test]
+    fn binary_search_finds_every_number_within_the_bound() {
+        for range in [1..=1, 1..=10, 1..=100, 7..=1000] {
+            let bound = binary_search_bound(&range);
+
+            for secret in range.clone() {
+                let mut game = GuessingGame::with_secret(&settings(range.clone()), secret);
+                let outcome = autoplay(&mut game, &mut BinarySearch::default(), &mut io::sink()).unwrap();
+
+                assert!(matches!(outcome, GuessOutcome::Won { attempts } if attempts <= bound), "{secret}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_bound_is_log2() {
+        assert_eq!(1, binary_search_bound(&(1..=1)));
+        assert_eq!(4, binary_search_bound(&(1..=10)));
+        assert_eq!(7, binary_search_bound(&(1..=100)));
+        assert_eq!(7, binary_search_bound(&(1..=127)));
+        assert_eq!(8, binary_search_bound(&(1..=128)));
+        assert_eq!(10, binary_search_bound(&(1..=1000)));
+        assert_eq!(33, binary_search_bound(&(0..=u32::MAX)));
+    }
+
+    #[test]
+    fn autoplay_prints_like_a_player() {
+        let mut game = GuessingGame::with_secret(&settings(1..=10), 8);
+        let mut output = Vec::new();
+
+        let outcome = autoplay(&mut game, &mut BinarySearch::default(), &mut output).unwrap();
+
+        assert_eq!(
+            "\
+binary guessed: 5
+Too small!
+binary guessed: 8
+binary wins! It took 2 attempts.
+",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(GuessOutcome::Won { attempts: 2 }, outcome);
+    }
+
+    #[test]
+    fn linear_runs_out_of_attempts() {
+        let settings = Settings { range: 1..=100, max_attempts: Some(7), debug: false };
+        let mut game = GuessingGame::with_secret(&settings, 50);
+
+        let outcome = autoplay(&mut game, &mut Linear::default(), &mut io::sink()).unwrap();
+
+        assert_eq!(GuessOutcome::Lost { secret: 50 }, outcome);
+    }
+
+    #[test]
+    fn bench_is_repeatable_and_halving_wins() {
+        let first = bench(&STRATEGIES, 1..=100, 200, 42);
+        let second = bench(&STRATEGIES, 1..=100, 200, 42);
+        assert_eq!(first, second);
+
+        let [binary, random, linear] = &first[..] else {
+            panic!("{first:?}");
+        };
+        assert!(first.iter().all(|result| result.games() == 200));
+        assert!(binary.max() <= 7);
+        assert!(binary.mean() < random.mean() && random.mean() < linear.mean());
+        // counting up takes about half the range on average
+        assert!((40.0..60.0).contains(&linear.mean()));
+    }
+
+    #[test]
+    fn report_has_a_row_per_attempt_count() {
+        let result = BenchResult { name: "binary", distribution: BTreeMap::from([(1, 1), (2, 2), (3, 4)]) };
+
+        assert_eq!(
+            "\
+binary: mean 2.43, max 3
+          1 ########## 1
+          2 #################### 2
+          3 ######################################## 4
+",
+            result.to_string()
+        );
+
+        let long = BenchResult { name: "linear", distribution: BTreeMap::from([(1, 1), (95, 1), (100, 2)]) };
+        assert!(long.to_string().ends_with("     91-100 ######################################## 3\n"));
+    }
+
+    #[test]
+    fn bench_options() {
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>().into_iter();
+
+        let report = run_bench(args(&["--games", "20", "--range", "1-1000", "--seed", "3"])).unwrap();
+        assert!(report.starts_with("20 games on 1-1000, halving needs at most 10 guesses\nbinary: mean"));
+        assert!(run_bench(args(&["--games", "0"])).is_err());
+        assert!(run_bench(args(&["--fast"])).is_err());
+    }
+}
@@ -1,7 +1,14 @@
-use std::{ fs::File, io::{ self, Read } };
+mod settings;
 
-fn read_username_from_file() -> Result<String, io::Error> {
-    // let username_file_result = File::open("hello.txt");
+use std::{ env, fs::File, io::{ self, Read }, path::Path };
+
+use crate::report::{ Context, report };
+
+use settings::{ ConfigLoadError, Loader, Resolved };
+
+// The file used to be hello.txt every time, now the caller says which.
+fn read_username_from_file(path: &Path) -> Result<String, io::Error> {
+    // let username_file_result = File::open(path);
 
     // let mut username_file = match username_file_result {
     //     Ok(file) => file,
@@ -22,12 +29,25 @@ fn read_username_from_file() -> Result<String, io::Error> {
     // The ? placed after a Result value is defined to work in almost
     // the same way as the match expressions we defined to handle the
     // Result values
-    let mut username_file = File::open("hello.txt")?;
+    let mut username_file = File::open(path)?;
     let mut username = String::new();
     username_file.read_to_string(&mut username)?;
     Ok(username)
 }
 
+// ? works the same with our own error type: a ConfigLoadError from `load`
+// or `require` goes straight back to the caller, file and line included.
+fn read_username() -> Result<Resolved, ConfigLoadError> {
+    let settings = Loader::new("HELLO")
+        .cli_args(env::args().skip(1))
+        .file("hello.toml")
+        .default("username", "guest")
+        .load()?;
+
+    let username = settings.require("username")?;
+    Ok(username.clone())
+}
+
 pub fn run() {
     // unrecoverable errors with panic!
     // panic!("crash and burn");
@@ -53,5 +73,10 @@ pub fn run() {
     //     "hello.txt should be included in this project"
     // );
 
-    read_username_from_file();
+    // the Result isn't thrown away any more: on an error, say what we were
+    // doing and everything that went wrong underneath
+    match read_username().context("reading the username") {
+        Ok(username) => println!("Hello, {}! (from {})", username.value, username.source),
+        Err(e) => println!("{}", report(&e)),
+    }
 }
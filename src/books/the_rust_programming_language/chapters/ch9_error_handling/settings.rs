@@ -0,0 +1,764 @@
+// Settings from several places at once
+//
+// A setting such as the username can come from, most important first:
+//
+//   1. the command line      --username=ferris or --username ferris
+//   2. the environment       HELLO_USERNAME=ferris
+//   3. a settings file       hello.toml, hello.json, or a plain hello.txt
+//                            that holds nothing but the username
+//   4. a default             built into the program
+//
+// The first place that has it wins, and `Settings::source` tells which one it
+// was. Anything that goes wrong on the way is a `ConfigLoadError`, which knows
+// the file and the line it was on, so the `?` examples in mod.rs can pass it
+// up without losing where it came from.
+
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt,
+    io,
+    path::{ Path, PathBuf },
+};
+
+use super::read_username_from_file;
+
+#[derive(Debug)]
+pub enum ConfigLoadError {
+    // the file is there but couldn't be read
+    Io { path: PathBuf, source: io::Error },
+    // `line` counts from 1
+    Parse { path: PathBuf, line: usize, message: String },
+    // none of the sources had the setting, and it has no default
+    Missing { key: String },
+}
+
+impl fmt::Display for ConfigLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigLoadError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            ConfigLoadError::Parse { path, line, message } => {
+                write!(f, "{}:{line}: {message}", path.display())
+            }
+            ConfigLoadError::Missing { key } => write!(f, "no value for '{key}'"),
+        }
+    }
+}
+
+impl Error for ConfigLoadError {
+    // the io::Error underneath, for whoever wants the whole chain
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigLoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    // the flag it was given with
+    Cli(String),
+    // the variable it was read from
+    Env(String),
+    File { path: PathBuf, line: usize },
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Cli(flag) => write!(f, "command line ({flag})"),
+            Source::Env(var) => write!(f, "environment (${var})"),
+            Source::File { path, line } => write!(f, "{}:{line}", path.display()),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+    pub value: String,
+    pub source: Source,
+}
+
+// Every setting that was found, with where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    values: HashMap<String, Resolved>,
+}
+
+impl Settings {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|resolved| resolved.value.as_str())
+    }
+
+    pub fn source(&self, key: &str) -> Option<&Source> {
+        self.values.get(key).map(|resolved| &resolved.source)
+    }
+
+    pub fn require(&self, key: &str) -> Result<&Resolved, ConfigLoadError> {
+        self.values.get(key).ok_or(ConfigLoadError::Missing { key: key.to_string() })
+    }
+}
+
+// Collects the sources, then `load` puts them together.
+pub struct Loader {
+    // HELLO for HELLO_USERNAME
+    env_prefix: String,
+    cli: HashMap<String, Resolved>,
+    env: HashMap<String, String>,
+    file: Option<PathBuf>,
+    defaults: HashMap<String, String>,
+}
+
+impl Loader {
+    pub fn new(env_prefix: &str) -> Loader {
+        Loader {
+            env_prefix: env_prefix.to_string(),
+            cli: HashMap::new(),
+            env: env::vars().collect(),
+            file: None,
+            defaults: HashMap::new(),
+        }
+    }
+
+    // Instead of the real environment, which tests can't change safely.
+    pub fn with_env(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Loader {
+        self.env = vars.into_iter().collect();
+        self
+    }
+
+    // Picks `--key=value` and `--key value` out of `args`, anything else is
+    // left for someone else.
+    pub fn cli_args(mut self, args: impl IntoIterator<Item = String>) -> Loader {
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                continue;
+            };
+            let (key, value) = match flag.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => {
+                    match args.next_if(|next| !next.starts_with("--")) {
+                        Some(value) => (flag.to_string(), value),
+                        None => {
+                            continue;
+                        }
+                    }
+                }
+            };
+            let source = Source::Cli(format!("--{key}"));
+            self.cli.insert(key, Resolved { value, source });
+        }
+        self
+    }
+
+    // A file that isn't there is skipped, like a variable that isn't set.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Loader {
+        self.file = Some(path.into());
+        self
+    }
+
+    pub fn default(mut self, key: &str, value: &str) -> Loader {
+        self.defaults.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    fn env_var(&self, key: &str) -> String {
+        format!("{}_{}", self.env_prefix, key.replace(['.', '-'], "_")).to_uppercase()
+    }
+
+    pub fn load(self) -> Result<Settings, ConfigLoadError> {
+        let from_file = match &self.file {
+            Some(path) => read_file(path)?,
+            None => HashMap::new(),
+        };
+
+        // every key any of the sources knows about
+        let mut keys: Vec<&String> = self.cli.keys().chain(from_file.keys()).chain(self.defaults.keys()).collect();
+        let prefix = format!("{}_", self.env_prefix.to_uppercase());
+        let env_keys: Vec<String> = self.env
+            .keys()
+            .filter_map(|var| var.strip_prefix(&prefix))
+            .map(str::to_lowercase)
+            .collect();
+        keys.extend(&env_keys);
+
+        let mut values = HashMap::new();
+        for key in keys {
+            let var = self.env_var(key);
+            let resolved = if let Some(resolved) = self.cli.get(key) {
+                resolved.clone()
+            } else if let Some(value) = self.env.get(&var) {
+                Resolved { value: value.clone(), source: Source::Env(var) }
+            } else if let Some(resolved) = from_file.get(key) {
+                resolved.clone()
+            } else if let Some(value) = self.defaults.get(key) {
+                Resolved { value: value.clone(), source: Source::Default }
+            } else {
+                continue;
+            };
+            values.insert(key.clone(), resolved);
+        }
+        Ok(Settings { values })
+    }
+}
+
+// The settings in a file, by its extension. Anything that isn't .toml or
+// .json is the old hello.txt: the whole file is the username.
+fn read_file(path: &Path) -> Result<HashMap<String, Resolved>, ConfigLoadError> {
+    let text = match read_username_from_file(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(HashMap::new());
+        }
+        Err(source) => {
+            return Err(ConfigLoadError::Io { path: path.to_path_buf(), source });
+        }
+    };
+
+    let pairs = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => parse_toml(&text),
+        Some("json") => parse_json(&text),
+        _ => Ok(vec![(String::from("username"), text.trim().to_string(), 1)]),
+    };
+    let pairs = pairs.map_err(|(line, message)| ConfigLoadError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    })?;
+
+    Ok(
+        pairs
+            .into_iter()
+            .map(|(key, value, line)| {
+                (key, Resolved { value, source: Source::File { path: path.to_path_buf(), line } })
+            })
+            .collect()
+    )
+}
+
+// (key, value, line) for every setting, or the line and what's wrong with it.
+type Pairs = Result<Vec<(String, String, usize)>, (usize, String)>;
+
+// Just enough TOML for settings: `key = value` lines with a string, a number
+// or a boolean, `[section]` headers (their keys become "section.key") and
+// `#` comments.
+pub fn parse_toml(text: &str) -> Pairs {
+    let mut pairs = Vec::new();
+    let mut section = String::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name.strip_suffix(']').ok_or((number, String::from("expected ']'")))?;
+            section = format!("{}.", name.trim());
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or((number, format!("expected 'key = value', found '{line}'")))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err((number, String::from("missing key before '='")));
+        }
+
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let (string, rest) = toml_string(quoted).map_err(|message| (number, message))?;
+            if !rest.trim().is_empty() && !rest.trim().starts_with('#') {
+                return Err((number, format!("unexpected '{}' after the string", rest.trim())));
+            }
+            string
+        } else {
+            // a number or a boolean, maybe with a comment after it
+            let bare = value.split('#').next().unwrap_or_default().trim();
+            if bare != "true" && bare != "false" && !is_toml_number(bare) {
+                return Err((number, format!("'{bare}' isn't a string, a number or a boolean")));
+            }
+            bare.to_string()
+        };
+        pairs.push((format!("{section}{key}"), value, number));
+    }
+    Ok(pairs)
+}
+
+// The string at the start of `text` (after its opening quote) and whatever
+// comes after the closing one.
+fn toml_string(text: &str) -> Result<(String, &str), String> {
+    let mut string = String::new();
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                return Ok((string, &text[i + 1..]));
+            }
+            '\\' => {
+                let (at, escaped) = chars.next().ok_or("unterminated string")?;
+                string.push(match escaped {
+                    'b' => '\u{8}',
+                    't' => '\t',
+                    'n' => '\n',
+                    'f' => '\u{c}',
+                    'r' => '\r',
+                    '"' => '"',
+                    '\\' => '\\',
+                    // \u0041 or \U00000041, which has to be a real character
+                    // (TOML has no surrogate pairs, and no \/ either)
+                    'u' | 'U' => {
+                        let len = if escaped == 'u' { 4 } else { 8 };
+                        let c = text
+                            .get(at + 1..at + 1 + len)
+                            .and_then(hex)
+                            .and_then(char::from_u32)
+                            .ok_or(format!("invalid \\{escaped} escape"))?;
+                        // the digits are ASCII, one char each
+                        chars.nth(len - 1);
+                        c
+                    }
+                    other => {
+                        return Err(format!("unknown escape '\\{other}'"));
+                    }
+                });
+            }
+            _ => string.push(c),
+        }
+    }
+    Err(String::from("unterminated string"))
+}
+
+// TOML's integers and floats: an optional sign, '_' between digits, 0x, 0o
+// and 0b integers, inf and nan.
+fn is_toml_number(word: &str) -> bool {
+    let unsigned = word.strip_prefix(['+', '-']).unwrap_or(word);
+    if matches!(unsigned, "inf" | "nan") {
+        return true;
+    }
+    for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = word.strip_prefix(prefix) {
+            return toml_digits(digits, radix);
+        }
+    }
+    is_decimal(unsigned, |digits| toml_digits(digits, 10))
+}
+
+fn toml_digits(digits: &str, radix: u32) -> bool {
+    digits.split('_').all(|run| !run.is_empty() && run.chars().all(|c| c.is_digit(radix)))
+}
+
+// JSON's numbers: -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?, so not NaN,
+// inf or infinity, which `f64::from_str` would take.
+fn is_json_number(word: &str) -> bool {
+    let unsigned = word.strip_prefix('-').unwrap_or(word);
+    is_decimal(unsigned, |digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+// int(.fraction)?(e[+-]?exponent)? without a leading zero, where `digits`
+// says what a run of digits may look like.
+fn is_decimal(word: &str, digits: impl Fn(&str) -> bool) -> bool {
+    let (mantissa, exponent) = match word.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))),
+        None => (word, None),
+    };
+    let (int, fraction) = match mantissa.split_once('.') {
+        Some((int, fraction)) => (int, Some(fraction)),
+        None => (mantissa, None),
+    };
+
+    digits(int) &&
+        (int == "0" || !int.starts_with('0')) &&
+        fraction.is_none_or(&digits) &&
+        exponent.is_none_or(&digits)
+}
+
+// The code point written as exactly these hex digits.
+fn hex(digits: &str) -> Option<u32> {
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok()
+}
+
+// Just enough JSON for settings: one object, whose values are strings,
+// numbers, booleans or objects of their own (their keys become
+// "outer.inner").
+pub fn parse_json(text: &str) -> Pairs {
+    let mut parser = JsonParser { text, at: 0 };
+    let mut pairs = Vec::new();
+
+    parser.skip_space();
+    parser.object("", &mut pairs)?;
+    parser.skip_space();
+    if parser.at < text.len() {
+        return Err(parser.error("unexpected text after the object"));
+    }
+    Ok(pairs)
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    // a byte offset into `text`
+    at: usize,
+}
+
+impl JsonParser<'_> {
+    fn line(&self) -> usize {
+        self.text[..self.at].matches('\n').count() + 1
+    }
+
+    fn error(&self, message: &str) -> (usize, String) {
+        (self.line(), message.to_string())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.at..].chars().next()
+    }
+
+    // Only the four that JSON allows, all one byte long. Other Unicode
+    // spaces (like U+00A0) are an error, as they are in any JSON parser.
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(is_json_space) {
+            self.at += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), (usize, String)> {
+        self.skip_space();
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{c}'")));
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    fn object(&mut self, prefix: &str, pairs: &mut Vec<(String, String, usize)>) -> Result<(), (usize, String)> {
+        self.expect('{')?;
+        self.skip_space();
+        if self.peek() == Some('}') {
+            self.at += 1;
+            return Ok(());
+        }
+
+        loop {
+            self.skip_space();
+            let line = self.line();
+            let key = format!("{prefix}{}", self.string()?);
+            self.expect(':')?;
+            self.skip_space();
+
+            match self.peek() {
+                Some('{') => self.object(&format!("{key}."), pairs)?,
+                Some('"') => {
+                    let value = self.string()?;
+                    pairs.push((key, value, line));
+                }
+                _ => {
+                    let value = self.bare()?;
+                    pairs.push((key, value, line));
+                }
+            }
+
+            self.skip_space();
+            match self.peek() {
+                Some(',') => {
+                    self.at += 1;
+                }
+                Some('}') => {
+                    self.at += 1;
+                    return Ok(());
+                }
+                _ => {
+                    return Err(self.error("expected ',' or '}'"));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, (usize, String)> {
+        if self.peek() != Some('"') {
+            return Err(self.error("expected a string"));
+        }
+        self.at += 1;
+
+        let mut string = String::new();
+        while let Some(c) = self.peek() {
+            self.at += c.len_utf8();
+            match c {
+                '"' => {
+                    return Ok(string);
+                }
+                '\\' => {
+                    let escaped = self.peek().ok_or(self.error("unterminated string"))?;
+                    self.at += escaped.len_utf8();
+                    string.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        other => {
+                            return Err(self.error(&format!("unknown escape '\\{other}'")));
+                        }
+                    });
+                }
+                '\n' => {
+                    return Err((self.line() - 1, String::from("unterminated string")));
+                }
+                _ => string.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    // The four hex digits after \u, and a second \uXXXX after them when the
+    // first is the high half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, (usize, String)> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                if !self.text[self.at..].starts_with("\\u") {
+                    return Err(self.error("unpaired surrogate in \\u escape"));
+                }
+                self.at += 2;
+                let low = self.hex4()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(self.error("unpaired surrogate in \\u escape"));
+                }
+                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+            }
+            _ => high,
+        };
+        // a lone low surrogate isn't a character
+        char::from_u32(code).ok_or(self.error("unpaired surrogate in \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, (usize, String)> {
+        let code = self.text
+            .get(self.at..self.at + 4)
+            .and_then(hex)
+            .ok_or(self.error("invalid \\u escape"))?;
+        self.at += 4;
+        Ok(code)
+    }
+
+    // true, false, null or a number
+    fn bare(&mut self) -> Result<String, (usize, String)> {
+        let rest = &self.text[self.at..];
+        let end = rest
+            .find(|c: char| c == ',' || c == '}' || is_json_space(c))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+
+        if !matches!(word, "true" | "false" | "null") && !is_json_number(word) {
+            return Err(self.error(&format!("'{word}' isn't a string, a number or a boolean")));
+        }
+        self.at += end;
+        Ok(word.to_string())
+    }
+}
+
+fn is_json_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ fs, process };
+
+    fn vars(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter()
+            .map(|a| a.to_string())
+            .collect()
+    }
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("ch9-{}-{name}", process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn the_first_source_that_has_it_wins() {
+        let file = temp_file("layers.toml", "username = \"from-file\"\ncolor = \"blue\"\nsize = 3\n");
+        let loader = || {
+            Loader::new("HELLO").file(&file).default("username", "guest").default("lang", "en")
+        };
+
+        let settings = loader()
+            .with_env(vars(&[("HELLO_USERNAME", "from-env"), ("HELLO_COLOR", "red"), ("PATH", "/bin")]))
+            .cli_args(args(&["--username", "from-cli", "positional"]))
+            .load()
+            .unwrap();
+
+        assert_eq!(Some("from-cli"), settings.get("username"));
+        assert_eq!(Some(&Source::Cli(String::from("--username"))), settings.source("username"));
+        assert_eq!(Some("red"), settings.get("color"));
+        assert_eq!(Some(&Source::Env(String::from("HELLO_COLOR"))), settings.source("color"));
+        assert_eq!(Some("3"), settings.get("size"));
+        assert_eq!(Some(&Source::File { path: file.clone(), line: 3 }), settings.source("size"));
+        assert_eq!(Some(&Source::Default), settings.source("lang"));
+        assert_eq!(None, settings.get("path"));
+
+        // take the command line and the environment away, the file shows
+        let settings = loader().with_env(vec![]).load().unwrap();
+        assert_eq!(Some("from-file"), settings.get("username"));
+        assert_eq!(format!("{}:1", file.display()), settings.source("username").unwrap().to_string());
+    }
+
+    #[test]
+    fn missing_files_are_skipped_and_missing_keys_reported() {
+        let settings = Loader::new("HELLO")
+            .with_env(vec![])
+            .cli_args(args(&["--lang=fr"]))
+            .file("/no/such/hello.toml")
+            .load()
+            .unwrap();
+
+        assert_eq!(Some("fr"), settings.get("lang"));
+        let error = settings.require("username").unwrap_err();
+        assert_eq!("no value for 'username'", error.to_string());
+    }
+
+    #[test]
+    fn a_plain_file_is_the_username() {
+        let file = temp_file("hello.txt", "ferris\n");
+
+        let settings = Loader::new("HELLO").with_env(vec![]).file(&file).load().unwrap();
+
+        assert_eq!(Some("ferris"), settings.get("username"));
+    }
+
+    #[test]
+    fn parse_errors_know_the_file_and_line() {
+        let file = temp_file("broken.toml", "# settings\nusername = \"ferris\"\ncolor = blue\n");
+
+        let error = Loader::new("HELLO").with_env(vec![]).file(&file).load().unwrap_err();
+
+        assert_eq!(format!("{}:3: 'blue' isn't a string, a number or a boolean", file.display()), error.to_string());
+        assert!(matches!(error, ConfigLoadError::Parse { line: 3, .. }));
+    }
+
+    #[test]
+    fn io_errors_keep_their_source() {
+        // a directory can be opened but not read as a file
+        let dir = env::temp_dir();
+
+        let error = Loader::new("HELLO").with_env(vec![]).file(&dir).load().unwrap_err();
+
+        assert!(matches!(error, ConfigLoadError::Io { .. }));
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn toml_sections_and_strings() {
+        let pairs = parse_toml("name = \"a \\\"quoted\\\" # name\" # comment\n\n[server]\nport = 8080\n").unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("name"), String::from("a \"quoted\" # name"), 1),
+                (String::from("server.port"), String::from("8080"), 4)
+            ],
+            pairs
+        );
+        assert_eq!(Err((1, String::from("unterminated string"))), parse_toml("name = \"open"));
+        assert_eq!(Err((2, String::from("expected ']'"))), parse_toml("\n[server"));
+    }
+
+    #[test]
+    fn toml_escapes() {
+        let value = |text: &str| parse_toml(text).map(|pairs| pairs[0].1.clone());
+
+        assert_eq!(Ok(String::from("\u{e9}\u{1f980}")), value("a = \"\\u00e9\\U0001F980\""));
+        assert_eq!(Ok(String::from("\r\u{8}\u{c}\t\n\"\\")), value("a = \"\\r\\b\\f\\t\\n\\\"\\\\\""));
+        assert_eq!(Err((1, String::from("unknown escape '\\/'"))), value("a = \"\\/\""));
+        assert_eq!(Err((1, String::from("unknown escape '\\q'"))), value("a = \"\\q\""));
+        assert_eq!(Err((1, String::from("invalid \\u escape"))), value("a = \"\\u00g9\""));
+        assert_eq!(Err((1, String::from("invalid \\u escape"))), value("a = \"\\ud83e\""));
+        assert_eq!(Err((2, String::from("invalid \\U escape"))), value("\na = \"\\U1\""));
+    }
+
+    #[test]
+    fn toml_numbers() {
+        for number in ["0", "-17", "+1_000", "3.14", "6.02e+23", "1E-7", "0xdead_beef", "0o755", "0b1010", "inf", "-nan"] {
+            assert!(parse_toml(&format!("a = {number}")).is_ok(), "{number}");
+        }
+        for word in ["NaN", "infinity", "Infinity", "012", "1__0", "_1", "1.", ".5", "1e", "+0x1", "0xg"] {
+            assert!(parse_toml(&format!("a = {word}")).is_err(), "{word}");
+        }
+    }
+
+    #[test]
+    fn json_objects_and_lines() {
+        let text = "{\n  \"username\": \"ferris\",\n  \"server\": {\n    \"port\": 8080,\n    \"tls\": false\n  }\n}\n";
+
+        assert_eq!(
+            vec![
+                (String::from("username"), String::from("ferris"), 2),
+                (String::from("server.port"), String::from("8080"), 4),
+                (String::from("server.tls"), String::from("false"), 5)
+            ],
+            parse_json(text).unwrap()
+        );
+        assert_eq!(Err((3, String::from("expected ',' or '}'"))), parse_json("{\n\"a\": 1\n\"b\": 2}"));
+        assert_eq!(Err((1, String::from("'yes' isn't a string, a number or a boolean"))), parse_json("{\"a\": yes}"));
+        assert_eq!(Err((1, String::from("unexpected text after the object"))), parse_json("{} {}"));
+    }
+
+    #[test]
+    fn json_escapes() {
+        let value = |text: &str| parse_json(text).map(|pairs| pairs[0].1.clone());
+
+        assert_eq!(Ok(String::from("\u{e9}/\r\u{8}\u{c}\t\n\"\\")), value("{\"a\": \"\\u00e9\\/\\r\\b\\f\\t\\n\\\"\\\\\"}"));
+        // U+1F980 as a surrogate pair
+        assert_eq!(Ok(String::from("\u{1f980}")), value("{\"a\": \"\\ud83e\\udd80\"}"));
+        assert_eq!(Err((1, String::from("unknown escape '\\q'"))), value("{\"a\": \"\\q\"}"));
+        assert_eq!(Err((2, String::from("invalid \\u escape"))), value("{\n\"a\": \"\\u00e\"}"));
+        assert_eq!(Err((1, String::from("unpaired surrogate in \\u escape"))), value("{\"a\": \"\\ud83e\"}"));
+        assert_eq!(Err((1, String::from("unpaired surrogate in \\u escape"))), value("{\"a\": \"\\ud83e\\u0041\"}"));
+        assert_eq!(Err((1, String::from("unpaired surrogate in \\u escape"))), value("{\"a\": \"\\udd80\"}"));
+
+        let file = temp_file("escape.json", "{\n\"username\": \"\\x41\"}");
+        let error = Loader::new("HELLO").with_env(vec![]).file(&file).load().unwrap_err();
+        assert_eq!(format!("{}:2: unknown escape '\\x'", file.display()), error.to_string());
+    }
+
+    #[test]
+    fn json_numbers() {
+        for number in ["0", "-0", "17", "-3.14", "6.02e23", "1E+2", "2e-7"] {
+            assert!(parse_json(&format!("{{\"a\": {number}}}")).is_ok(), "{number}");
+        }
+        for word in ["NaN", "inf", "-infinity", "Infinity", "+1", "012", "1.", ".5", "1e", "0x10", "1_000"] {
+            assert!(parse_json(&format!("{{\"a\": {word}}}")).is_err(), "{word}");
+        }
+    }
+
+    #[test]
+    fn json_non_ascii_space_is_an_error() {
+        // a non-breaking space, two bytes long
+        assert_eq!(Err((1, String::from("expected a string"))), parse_json("{\u{a0}\"a\": 1}"));
+        assert_eq!(Err((2, String::from("'1\u{a0}' isn't a string, a number or a boolean"))), parse_json("{\n\"a\": 1\u{a0}}"));
+
+        let file = temp_file("nbsp.json", "{\u{a0}\"username\": \"ferris\"}");
+        let error = Loader::new("HELLO").with_env(vec![]).file(&file).load().unwrap_err();
+        assert!(matches!(error, ConfigLoadError::Parse { line: 1, .. }));
+    }
+}
@@ -14,6 +14,8 @@
 use std::error::Error;
 use std::fmt;
 
+use crate::report::{ Context, report };
+
 #[derive(PartialEq, Debug)]
 enum CreationError {
     Negative,
@@ -50,7 +52,23 @@ impl PositiveNonzeroInteger {
 // use to describe both errors? Is there a trait which both errors implement?
 pub fn run() -> Result<(), Box<dyn Error>> {
     let pretend_user_input = "42";
-    let x: i64 = pretend_user_input.parse()?;
-    println!("output={:?}", PositiveNonzeroInteger::new(x)?);
+    // `.context` says what was going on, the error it wraps is kept as the
+    // cause, and `?` still boxes either one
+    let x: i64 = pretend_user_input
+        .parse()
+        .with_context(|| format!("reading {pretend_user_input:?} as a number"))?;
+    let number = PositiveNonzeroInteger::new(x).context("checking the number")?;
+    println!("output={number:?}");
     Ok(())
 }
+
+// `run`, with the whole chain printed if it fails:
+//   Error: checking the number
+//
+//   Caused by:
+//       0: number is negative
+pub fn run_and_report() {
+    if let Err(e) = run() {
+        println!("{}", report(e.as_ref()));
+    }
+}
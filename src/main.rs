@@ -5,6 +5,8 @@
 #![allow(unused_must_use)]
 
 mod books;
+mod report;
+mod temp_file;
 
 // abbreviation
 
@@ -132,6 +134,7 @@ fn main() {
     // 13_error_handling
     // RL::sol_13_error_handling::errors3::run();
     // RL::sol_13_error_handling::errors5::run();
+    // RL::sol_13_error_handling::errors5::run_and_report();
 
     // 14_generics
     // RL::sol_14_generics::generics1::run();
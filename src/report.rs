@@ -0,0 +1,206 @@
+// Errors that say what was going on when they happened
+//
+// `?` passes an error up as it is, so by the time `main` prints it all that's
+// left is "No such file or directory (os error 2)": which file, and what for?
+// `.context(...)` wraps the error in a `ContextError` that says what was being
+// done, and keeps the original as its `source()`, so nothing is lost:
+//
+//     let text = fs::read_to_string(path).context("reading username file")?;
+//
+// `report` then prints the whole chain, outermost first:
+//
+//     Error: reading username file
+//
+//     Caused by:
+//         0: No such file or directory (os error 2)
+//
+// With RUST_BACKTRACE=1 (or RUST_LIB_BACKTRACE=1) set, a backtrace of where
+// the context was added is kept too, and printed at the end.
+
+use std::{
+    backtrace::{ Backtrace, BacktraceStatus },
+    error::Error,
+    fmt,
+};
+
+pub struct ContextError {
+    message: String,
+    source: Box<dyn Error>,
+    backtrace: Backtrace,
+}
+
+impl ContextError {
+    pub fn new(message: impl fmt::Display, source: impl Into<Box<dyn Error>>) -> ContextError {
+        ContextError {
+            message: message.to_string(),
+            source: source.into(),
+            // does nothing unless the environment asks for backtraces
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self.backtrace.status() {
+            BacktraceStatus::Captured => Some(&self.backtrace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// by hand, so that `{:?}` (what `main` returning an Err prints) is the whole
+// report and not the struct
+impl fmt::Debug for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Report(self))
+    }
+}
+
+impl Error for ContextError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+pub trait Context<T> {
+    fn context(self, message: impl fmt::Display) -> Result<T, ContextError>;
+
+    // For messages that cost something to build, like a `format!`: only
+    // built when there is an error.
+    fn with_context<M: fmt::Display>(self, message: impl FnOnce() -> M) -> Result<T, ContextError>;
+}
+
+// Anything that `?` could turn into a Box<dyn Error>: real errors, boxed
+// ones, and the Strings and &strs some of the exercises fail with.
+impl<T, E: Into<Box<dyn Error>>> Context<T> for Result<T, E> {
+    fn context(self, message: impl fmt::Display) -> Result<T, ContextError> {
+        self.map_err(|e| ContextError::new(message, e))
+    }
+
+    fn with_context<M: fmt::Display>(self, message: impl FnOnce() -> M) -> Result<T, ContextError> {
+        self.map_err(|e| ContextError::new(message(), e))
+    }
+}
+
+// Shows an error with all of its causes, see the top of the file.
+pub struct Report<'a>(pub &'a (dyn Error + 'static));
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error: {}", self.0)?;
+
+        let mut causes = Vec::new();
+        let mut next = self.0.source();
+        while let Some(cause) = next {
+            causes.push(cause);
+            next = cause.source();
+        }
+
+        if !causes.is_empty() {
+            write!(f, "\n\nCaused by:")?;
+            for (i, cause) in causes.iter().enumerate() {
+                write!(f, "\n    {i}: {cause}")?;
+            }
+        }
+
+        // the innermost one is closest to where things went wrong
+        let backtrace = [self.0]
+            .into_iter()
+            .chain(causes)
+            .filter_map(|error| error.downcast_ref::<ContextError>()?.backtrace())
+            .last();
+        if let Some(backtrace) = backtrace {
+            write!(f, "\n\nBacktrace:\n{backtrace}")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn report(error: &(dyn Error + 'static)) -> String {
+    Report(error).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ fs, io };
+
+    // The report without its backtrace, which is there or not depending on
+    // how the tests were started.
+    fn chain(error: &(dyn Error + 'static)) -> String {
+        let report = report(error);
+        report.split("\n\nBacktrace:\n").next().unwrap().to_string()
+    }
+
+    fn read_username(path: &str) -> Result<String, ContextError> {
+        let text = fs::read_to_string(path).with_context(|| format!("opening {path}"))?;
+        Ok(text)
+    }
+
+    #[test]
+    fn context_keeps_the_cause() {
+        let error = read_username("/no/such/hello.txt").context("reading username file").unwrap_err();
+
+        assert_eq!("reading username file", error.to_string());
+        let cause = error.source().unwrap();
+        assert_eq!("opening /no/such/hello.txt", cause.to_string());
+        let io_error = cause.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io::ErrorKind::NotFound, io_error.kind());
+    }
+
+    #[test]
+    fn report_lists_every_cause() {
+        let error = read_username("/no/such/hello.txt").context("reading username file").unwrap_err();
+
+        assert_eq!(
+            "\
+Error: reading username file
+
+Caused by:
+    0: opening /no/such/hello.txt
+    1: No such file or directory (os error 2)",
+            chain(&error)
+        );
+        assert_eq!(report(&error), format!("{error:?}"));
+    }
+
+    #[test]
+    fn plain_errors_and_strings() {
+        let parsed: Result<i64, _> = "forty-two".parse::<i64>();
+        assert_eq!("Error: invalid digit found in string", report(&parsed.unwrap_err()));
+
+        let failed: Result<(), &str> = Err("not enough arguments");
+        let error = failed.context("parsing arguments").unwrap_err();
+        assert_eq!("Error: parsing arguments\n\nCaused by:\n    0: not enough arguments", chain(&error));
+    }
+
+    #[test]
+    fn backtraces_come_last() {
+        let mut error = ContextError::new("reading username file", "no file");
+        error.backtrace = Backtrace::force_capture();
+
+        let report = report(&error);
+
+        assert!(report.starts_with("Error: reading username file\n\nCaused by:\n    0: no file\n\nBacktrace:\n"));
+        assert!(report.contains("backtraces_come_last"));
+    }
+
+    #[test]
+    fn with_context_is_lazy() {
+        let mut built = false;
+
+        let ok: Result<u8, io::Error> = Ok(1);
+        let value = ok.with_context(|| {
+            built = true;
+            "never shown"
+        });
+
+        assert_eq!(1, value.unwrap());
+        assert!(!built);
+    }
+}
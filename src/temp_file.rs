@@ -0,0 +1,45 @@
+// Temporary files that get renamed over the real one
+//
+// Writing straight into a file leaves it half written if the program dies in
+// the middle. Writing a temporary file next to it and renaming that over the
+// original doesn't: on the same filesystem a rename replaces the file in one
+// step. minigrep's --in-place and its index, and the guessing game's score
+// file, all save this way.
+//
+// Every temporary file gets a name of its own, from the pid and a counter,
+// and is created with `create_new`, so two programs (or two threads) saving
+// the same file at once never write into the same temporary file.
+
+use std::{
+    fs::{ File, OpenOptions },
+    io,
+    path::{ Path, PathBuf },
+    process,
+    sync::atomic::{ AtomicUsize, Ordering },
+};
+
+// numbers the temporary files of this process
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+// A new, empty file next to `target`, to be renamed over it. If the name is
+// taken anyway (left over from a crash, say) we move on to the next one
+// instead of sharing it.
+pub fn create_next_to(target: &Path) -> io::Result<(PathBuf, File)> {
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+
+    loop {
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let temp = target.with_file_name(format!(".{}.{}-{n}.tmp", file_name.to_string_lossy(), process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => {
+                return Ok((temp, file));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e);
+            }
+        }
+    }
+}